@@ -0,0 +1,29 @@
+use super::*;
+
+/// Identifies an entry for annotations, so that a note survives changes to the entry's
+/// components, options, or whether it is enabled.
+pub(crate) type EntryKey = (bool, String, String);
+
+pub(crate) fn entry_key(entry: &SourceEntry) -> EntryKey {
+    (entry.source, entry.url.clone(), entry.suite.clone())
+}
+
+impl SourcesLists {
+    /// Attaches a transient note to an entry, such as who added it and when.
+    ///
+    /// Notes are kept in memory alongside the lists, and are never written to them. An entry
+    /// is identified by its type, URL, and suite, so the note follows it through other edits.
+    pub fn annotate<S: Into<String>>(&mut self, entry: &SourceEntry, note: S) {
+        self.annotations.insert(entry_key(entry), note.into());
+    }
+
+    /// The note attached to an entry, if there is one.
+    pub fn annotation(&self, entry: &SourceEntry) -> Option<&str> {
+        self.annotations.get(&entry_key(entry)).map(String::as_str)
+    }
+
+    /// Detaches the note from an entry, returning it.
+    pub fn remove_annotation(&mut self, entry: &SourceEntry) -> Option<String> {
+        self.annotations.remove(&entry_key(entry))
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::{Path, PathBuf};
+
+/// The locations of the sources lists, as resolved from apt's configuration.
+///
+/// These correspond to the `Dir::Etc::sourcelist` and `Dir::Etc::sourceparts` options of
+/// `apt.conf`, which advanced setups may override.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AptConfig {
+    /// The main sources list, **/etc/apt/sources.list** by default.
+    pub sourcelist: PathBuf,
+    /// The directory of additional lists, **/etc/apt/sources.list.d** by default.
+    pub sourceparts: PathBuf,
+}
+
+impl AptConfig {
+    /// The locations which apt uses when they are not overridden.
+    pub fn standard() -> Self {
+        Self::with_root(Path::new("/"))
+    }
+
+    /// The standard locations, within a `root` such as a chroot.
+    pub fn with_root<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref();
+        AptConfig {
+            sourcelist: root.join("etc/apt/sources.list"),
+            sourceparts: root.join("etc/apt/sources.list.d"),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+
+/// The kind of archive that an entry points to, which determines its layout conventions.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ArchiveKind {
+    /// The Ubuntu archive, or one of its mirrors under `ubuntu.com`.
+    Ubuntu,
+    /// The Debian archive, or one of its mirrors under `debian.org`.
+    Debian,
+    /// A Launchpad PPA.
+    Launchpad,
+    /// Any other repository.
+    ThirdParty,
+}
+
+impl SourceEntry {
+    /// Infers the kind of archive from the host of the URL.
+    pub fn archive_kind(&self) -> ArchiveKind {
+        let host = match self.host() {
+            Some(host) => host,
+            None => return ArchiveKind::ThirdParty,
+        };
+
+        if host_in_domain(host, "launchpad.net") || host_in_domain(host, "launchpadcontent.net") {
+            ArchiveKind::Launchpad
+        } else if host_in_domain(host, "ubuntu.com") {
+            ArchiveKind::Ubuntu
+        } else if host_in_domain(host, "debian.org") {
+            ArchiveKind::Debian
+        } else {
+            ArchiveKind::ThirdParty
+        }
+    }
+
+    /// Creates the entry of a Launchpad PPA from the `ppa:<owner>/<name>` shortcut which
+    /// `add-apt-repository` accepts, along with the name of the file to store it in, as given by
+    /// `ppa_list_filename`.
+    ///
+    /// Returns `SourceError::InvalidValue` if the shortcut is not of that form.
+    pub fn from_ppa(shortcut: &str, suite: &str) -> SourceResult<(SourceEntry, String)> {
+        let invalid = || SourceError::InvalidValue { field: "ppa", value: shortcut.into() };
+        if !shortcut.starts_with("ppa:") {
+            return Err(invalid());
+        }
+
+        let mut parts = shortcut[4..].split('/');
+        let (owner, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(name), None) => (owner, name),
+            _ => return Err(invalid()),
+        };
+
+        let valid = |part: &str| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        };
+
+        if !valid(owner) || !valid(name) || suite.is_empty() {
+            return Err(invalid());
+        }
+
+        let entry = SourceEntry {
+            enabled: true,
+            locked: false,
+            source: false,
+            options: None,
+            url: ["http://ppa.launchpad.net/", owner, "/", name, "/ubuntu"].concat(),
+            suite: suite.to_owned(),
+            components: vec!["main".into()],
+            comment: None,
+            comment_spacing: None,
+        };
+
+        let filename = entry.ppa_list_filename().ok_or_else(invalid)?;
+        Ok((entry, filename))
+    }
+
+    /// The `ppa:<owner>/<name>` shortcut of a Launchpad PPA entry.
+    pub fn ppa_shortcut(&self) -> Option<String> {
+        if self.archive_kind() != ArchiveKind::Launchpad {
+            return None;
+        }
+
+        let url = self.url();
+        let path = &url[url.find("://")? + 3..];
+        let mut parts = path.split('/').skip(1);
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(name), Some("ubuntu"), None) => {
+                Some(["ppa:", owner, "/", name].concat())
+            }
+            _ => None,
+        }
+    }
+
+    /// The `<owner>-ubuntu-<name>-<suite>.list` file which current releases of
+    /// `add-apt-repository` store a PPA entry in.
+    pub fn ppa_list_filename(&self) -> Option<String> {
+        self.ppa_shortcut().and_then(|_| self.list_filename())
+    }
+
+    /// The name of the file within **sources.list.d** which `add-apt-repository` would store
+    /// this entry in.
+    ///
+    /// PPAs are stored as `<owner>-ubuntu-<name>-<suite>.list`, and other repositories as
+    /// `<host>.list`.
+    /// Returns `None` for the Ubuntu and Debian archives, which belong in **sources.list**.
+    pub fn list_filename(&self) -> Option<String> {
+        let name = match self.archive_kind() {
+            ArchiveKind::Ubuntu | ArchiveKind::Debian => return None,
+            ArchiveKind::Launchpad => match self.ppa_shortcut() {
+                Some(ppa) => [&ppa[4..].replacen('/', "-ubuntu-", 1), "-", &self.suite].concat(),
+                None => self.host().unwrap_or_default().to_owned(),
+            },
+            ArchiveKind::ThirdParty => match self.host() {
+                Some(host) => host.to_owned(),
+                None => self.filename(),
+            },
+        };
+
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+
+        Some(name + ".list")
+    }
+}
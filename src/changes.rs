@@ -0,0 +1,97 @@
+use super::*;
+use std::fmt::{self, Display, Formatter};
+
+/// A field which differs between two versions of a `SourceEntry`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FieldChange {
+    /// The entry was enabled (`true`) or disabled (`false`).
+    Enabled(bool),
+    /// The entry was switched to source packages (`true`) or binaries (`false`).
+    Source(bool),
+    /// The options of the entry were changed.
+    Options { old: Option<String>, new: Option<String> },
+    /// The entry was pointed to a different URL.
+    Url { old: String, new: String },
+    /// The entry was pointed to a different suite.
+    Suite { old: String, new: String },
+    /// Components which were enabled on the entry.
+    ComponentsAdded(Vec<String>),
+    /// Components which were removed from the entry.
+    ComponentsRemoved(Vec<String>),
+}
+
+impl Display for FieldChange {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            FieldChange::Enabled(true) => fmt.write_str("enabled"),
+            FieldChange::Enabled(false) => fmt.write_str("disabled"),
+            FieldChange::Source(true) => fmt.write_str("changed from deb to deb-src"),
+            FieldChange::Source(false) => fmt.write_str("changed from deb-src to deb"),
+            FieldChange::Options { ref old, ref new } => write!(
+                fmt,
+                "options changed from '{}' to '{}'",
+                old.as_deref().unwrap_or(""),
+                new.as_deref().unwrap_or("")
+            ),
+            FieldChange::Url { ref old, ref new } => {
+                write!(fmt, "url changed from {} to {}", old, new)
+            }
+            FieldChange::Suite { ref old, ref new } => {
+                write!(fmt, "suite changed from {} to {}", old, new)
+            }
+            FieldChange::ComponentsAdded(ref components) => {
+                write!(fmt, "components added: {}", components.join(" "))
+            }
+            FieldChange::ComponentsRemoved(ref components) => {
+                write!(fmt, "components removed: {}", components.join(" "))
+            }
+        }
+    }
+}
+
+impl SourceEntry {
+    /// Lists each field which differs from the `old` version of this entry.
+    ///
+    /// Components are compared as a set, so reordering them is not a change.
+    pub fn changes_from(&self, old: &SourceEntry) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+
+        if self.enabled != old.enabled {
+            changes.push(FieldChange::Enabled(self.enabled));
+        }
+
+        if self.source != old.source {
+            changes.push(FieldChange::Source(self.source));
+        }
+
+        if self.options != old.options {
+            let (old, new) = (old.options.clone(), self.options.clone());
+            changes.push(FieldChange::Options { old, new });
+        }
+
+        if self.url != old.url {
+            changes.push(FieldChange::Url { old: old.url.clone(), new: self.url.clone() });
+        }
+
+        if self.suite != old.suite {
+            changes.push(FieldChange::Suite { old: old.suite.clone(), new: self.suite.clone() });
+        }
+
+        let added = components_missing(&self.components, &old.components);
+        if !added.is_empty() {
+            changes.push(FieldChange::ComponentsAdded(added));
+        }
+
+        let removed = components_missing(&old.components, &self.components);
+        if !removed.is_empty() {
+            changes.push(FieldChange::ComponentsRemoved(removed));
+        }
+
+        changes
+    }
+}
+
+/// The components of `a` which are not in `b`.
+fn components_missing(a: &[String], b: &[String]) -> Vec<String> {
+    a.iter().filter(|component| !b.contains(component)).cloned().collect()
+}
@@ -0,0 +1,564 @@
+use super::*;
+use annotations::entry_key;
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Option keys of the one-line format, and the fields which they are written as in a deb822
+/// stanza.
+const OPTION_FIELDS: &[(&str, &str)] = &[
+    ("arch", "Architectures"),
+    ("lang", "Languages"),
+    ("target", "Targets"),
+    ("pdiffs", "PDiffs"),
+    ("by-hash", "By-Hash"),
+    ("allow-insecure", "Allow-Insecure"),
+    ("allow-weak", "Allow-Weak"),
+    ("allow-downgrade-to-insecure", "Allow-Downgrade-To-Insecure"),
+    ("trusted", "Trusted"),
+    ("signed-by", "Signed-By"),
+    ("check-valid-until", "Check-Valid-Until"),
+    ("valid-until-min", "Valid-Until-Min"),
+    ("valid-until-max", "Valid-Until-Max"),
+    ("check-date", "Check-Date"),
+    ("date-max-future", "Date-Max-Future"),
+    ("inrelease-path", "InRelease-Path"),
+];
+
+/// Fields whose values are lists, which are comma-separated in the one-line format.
+const LIST_FIELDS: &[&str] = &["Architectures", "Languages", "Targets", "Signed-By"];
+
+/// Fields which define the entries of a stanza, rather than their options.
+const ENTRY_FIELDS: &[&str] = &["Types", "URIs", "Suites", "Components", "Enabled"];
+
+/// Whether the list at `path` is in the deb822 format, as `.sources` files are.
+pub(crate) fn is_deb822_path(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("sources"))
+}
+
+/// A line of a deb822 stanza.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Deb822Line {
+    /// A field, whose value is kept exactly as written after the colon, including any
+    /// continuation lines.
+    Field { name: String, value: String },
+    /// A comment within the stanza, which apt ignores.
+    Comment(String),
+}
+
+/// A stanza of a `.sources` file, which describes an entry for each combination of its
+/// `Types`, `URIs`, and `Suites`.
+///
+/// Fields and comments are kept in their original order and form, so that a stanza is written
+/// back exactly as it was parsed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Deb822Entry {
+    pub lines: Vec<Deb822Line>,
+}
+
+impl Display for Deb822Entry {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match *line {
+                Deb822Line::Field { ref name, ref value } => writeln!(fmt, "{}:{}", name, value)?,
+                Deb822Line::Comment(ref comment) => writeln!(fmt, "{}", comment)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Deb822Entry {
+    type Err = SourceError;
+    fn from_str(stanza: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |line: &str| SourceError::InvalidValue { field: "stanza", value: line.into() };
+
+        let mut entry = Deb822Entry::default();
+        for line in stanza.lines() {
+            if line.trim().is_empty() {
+                return Err(invalid(line));
+            } else if line.starts_with('#') {
+                entry.lines.push(Deb822Line::Comment(line.into()));
+            } else if line.starts_with(' ') || line.starts_with('\t') {
+                match entry.lines.iter_mut().rev().find_map(|line| match line {
+                    Deb822Line::Field { value, .. } => Some(value),
+                    _ => None,
+                }) {
+                    Some(value) => {
+                        value.push('\n');
+                        value.push_str(line);
+                    }
+                    None => return Err(invalid(line)),
+                }
+            } else {
+                let pos = line.find(':').ok_or_else(|| invalid(line))?;
+                let name = &line[..pos];
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(invalid(line));
+                }
+
+                let value = line[pos + 1..].into();
+                entry.lines.push(Deb822Line::Field { name: name.into(), value });
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+impl Deb822Entry {
+    /// The value of a field, without surrounding whitespace. Names are matched case-insensitively.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Deb822Line::Field { name: field, value } if field.eq_ignore_ascii_case(name) => {
+                Some(value.trim())
+            }
+            _ => None,
+        })
+    }
+
+    /// The entries which this stanza describes, with its other fields as their options.
+    ///
+    /// Entries are ordered by type, then URI, then suite, as they are given in the stanza.
+    pub fn to_entries(&self) -> SourceResult<Vec<SourceEntry>> {
+        let values = |name: &'static str| -> SourceResult<Vec<&str>> {
+            let values: Vec<&str> = self.field(name).unwrap_or("").split_whitespace().collect();
+            if values.is_empty() {
+                Err(SourceError::MissingField { field: name })
+            } else {
+                Ok(values)
+            }
+        };
+
+        let types = values("Types")?
+            .into_iter()
+            .map(|kind| match kind {
+                "deb" => Ok(false),
+                "deb-src" => Ok(true),
+                other => Err(SourceError::InvalidValue { field: "Types", value: other.into() }),
+            })
+            .collect::<SourceResult<Vec<bool>>>()?;
+
+        let uris = values("URIs")?;
+        let suites = values("Suites")?;
+        let components: Vec<String> =
+            self.field("Components").unwrap_or("").split_whitespace().map(String::from).collect();
+
+        let enabled = match self.field("Enabled") {
+            Some(value) => parse_bool(value).ok_or_else(|| SourceError::InvalidValue {
+                field: "Enabled",
+                value: value.into(),
+            })?,
+            None => true,
+        };
+
+        let mut options = Vec::new();
+        for line in &self.lines {
+            let (name, value) = match *line {
+                Deb822Line::Field { ref name, ref value }
+                    if !ENTRY_FIELDS.iter().any(|field| field.eq_ignore_ascii_case(name)) =>
+                {
+                    (name, value.trim())
+                }
+                _ => continue,
+            };
+
+            let (key, name) =
+                match OPTION_FIELDS.iter().find(|&&(_, f)| f.eq_ignore_ascii_case(name)) {
+                    Some(&(key, name)) => (key.to_owned(), name),
+                    None => (name.to_ascii_lowercase(), name.as_str()),
+                };
+
+            // Multi-line values, such as an embedded key, are kept as they are.
+            let value = if LIST_FIELDS.contains(&name) && !value.contains('\n') {
+                value.split_whitespace().collect::<Vec<_>>().join(",")
+            } else {
+                value.to_owned()
+            };
+
+            options.push(option_token(&key, &value));
+        }
+
+        let options = if options.is_empty() { None } else { Some(options.join(" ")) };
+
+        // Only flat repositories, whose suite is a path such as `./`, may lack components.
+        if components.is_empty() && suites.iter().any(|suite| !suite.ends_with('/')) {
+            return Err(SourceError::MissingField { field: "Components" });
+        }
+
+        let mut entries = Vec::new();
+        for &source in &types {
+            for uri in &uris {
+                for suite in &suites {
+                    entries.push(SourceEntry {
+                        enabled,
+                        locked: false,
+                        source,
+                        options: options.clone(),
+                        url: (*uri).to_owned(),
+                        suite: (*suite).to_owned(),
+                        components: components.clone(),
+                        comment: None,
+                        comment_spacing: None,
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Deb822Entry {
+    /// This stanza with its fields rewritten to describe the `entries`, keeping its comments,
+    /// the order of its fields, and the form of each field whose value is unchanged.
+    ///
+    /// Returns `None` if the entries cannot all be described by a single stanza.
+    fn updated(&self, entries: &[SourceEntry]) -> Option<Deb822Entry> {
+        fn push_unique<'a>(values: &mut Vec<&'a str>, value: &'a str) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+
+        let first = entries.first()?;
+        let (mut types, mut uris, mut suites) = (Vec::new(), Vec::new(), Vec::new());
+        for entry in entries {
+            push_unique(&mut types, if entry.source { "deb-src" } else { "deb" });
+            push_unique(&mut uris, &entry.url);
+            push_unique(&mut suites, &entry.suite);
+        }
+
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in first.to_deb822().parse::<Deb822Entry>().ok()?.lines {
+            if let Deb822Line::Field { name, value } = line {
+                let value = match name.as_str() {
+                    "Types" => types.join(" "),
+                    "URIs" => uris.join(" "),
+                    "Suites" => suites.join(" "),
+                    _ => value.trim().to_owned(),
+                };
+
+                fields.push((name, value));
+            }
+        }
+
+        let tokens = |value: &str| value.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut stanza = Deb822Entry::default();
+        for line in &self.lines {
+            let (name, value) = match *line {
+                Deb822Line::Field { ref name, ref value } => (name, value),
+                Deb822Line::Comment(_) => {
+                    stanza.lines.push(line.clone());
+                    continue;
+                }
+            };
+
+            match fields.iter().position(|(field, _)| field.eq_ignore_ascii_case(name)) {
+                Some(pos) => {
+                    let (_, new) = fields.remove(pos);
+                    if tokens(value) == tokens(&new) {
+                        stanza.lines.push(line.clone());
+                    } else {
+                        let value = [" ", &new].concat();
+                        stanza.lines.push(Deb822Line::Field { name: name.clone(), value });
+                    }
+                }
+                // An explicit `Enabled: yes` is kept while the entries remain enabled.
+                None if name.eq_ignore_ascii_case("Enabled")
+                    && first.enabled
+                    && parse_bool(value.trim()) == Some(true) =>
+                {
+                    stanza.lines.push(line.clone());
+                }
+                None => (),
+            }
+        }
+
+        for (name, value) in fields {
+            stanza.lines.push(Deb822Line::Field { name, value: [" ", &value].concat() });
+        }
+
+        if stanza.to_entries().ok()?.as_slice() == entries {
+            Some(stanza)
+        } else {
+            None
+        }
+    }
+}
+
+impl SourceEntry {
+    /// Renders this entry as a single deb822 stanza, as found in a `.sources` file.
+    ///
+    /// Options are written as their equivalent fields, such as `Signed-By:` for `signed-by`,
+    /// and `Enabled: no` is written if the entry is disabled.
+    pub fn to_deb822(&self) -> String {
+        let mut stanza = String::new();
+        let mut field = |name: &str, value: &str| {
+            stanza.push_str(name);
+            stanza.push_str(": ");
+            stanza.push_str(value);
+            stanza.push('\n');
+        };
+
+        field("Types", if self.source { "deb-src" } else { "deb" });
+        field("URIs", &self.url);
+        field("Suites", &self.suite);
+        if !self.components.is_empty() {
+            field("Components", &self.components_str());
+        }
+
+        for (key, value) in self.options.as_deref().into_iter().flat_map(option_pairs) {
+            let name = match OPTION_FIELDS.iter().find(|&&(k, _)| k == key) {
+                Some(&(_, name)) => name.to_owned(),
+                None => field_name(key),
+            };
+
+            if LIST_FIELDS.contains(&name.as_str()) {
+                field(&name, &value.replace(',', " "));
+            } else {
+                field(&name, value);
+            }
+        }
+
+        if !self.enabled {
+            field("Enabled", "no");
+        }
+
+        stanza
+    }
+}
+
+/// Converts an unrecognized option key to a field name, such as `foo-bar` to `Foo-Bar`.
+fn field_name(key: &str) -> String {
+    key.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// A block of a `.sources` file.
+enum Block<'a> {
+    /// A stanza, along with the index of its first line and its original text.
+    Stanza { line: usize, entry: Deb822Entry, raw: String },
+    /// A comment outside of any stanza.
+    Comment(&'a str),
+    /// An empty line, which separates stanzas.
+    Empty,
+}
+
+/// Splits the text of a `.sources` file into its stanzas, and the lines between them.
+fn blocks<'a>(input: &'a str, ending: &str) -> Result<Vec<Block<'a>>, SourcesListError> {
+    fn finish<'a>(
+        blocks: &mut Vec<Block<'a>>,
+        stanza: Option<(usize, Vec<&'a str>)>,
+        ending: &str,
+    ) -> Result<(), SourcesListError> {
+        if let Some((line, lines)) = stanza {
+            let entry = lines
+                .join("\n")
+                .parse::<Deb822Entry>()
+                .map_err(|why| SourcesListError::BadLine { line, text: lines[0].into(), why })?;
+
+            let raw = lines.iter().map(|line| [line, ending].concat()).collect();
+            blocks.push(Block::Stanza { line, entry, raw });
+        }
+
+        Ok(())
+    }
+
+    let mut blocks = Vec::new();
+    let mut stanza: Option<(usize, Vec<&str>)> = None;
+    for (no, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            finish(&mut blocks, stanza.take(), ending)?;
+            blocks.push(Block::Empty);
+        } else if stanza.is_none() && line.starts_with('#') {
+            blocks.push(Block::Comment(line));
+        } else {
+            stanza.get_or_insert_with(|| (no, Vec::new())).1.push(line);
+        }
+    }
+
+    finish(&mut blocks, stanza, ending)?;
+    Ok(blocks)
+}
+
+impl SourcesList {
+    /// Parses a list in the deb822 format of `.sources` files.
+    ///
+    /// Each stanza becomes a `SourceLine::Entry` for every entry that it describes, while
+    /// comments and empty lines between stanzas are kept as lines of their own.
+    pub fn parse_deb822(input: &str) -> Result<Self, SourcesListError> {
+        let mut list = SourcesList {
+            crlf: input.contains("\r\n"),
+            bom: input.starts_with(BOM),
+            deb822: true,
+            original: Some(input.to_owned()),
+            ..Self::default()
+        };
+
+        for block in blocks(input.trim_start_matches(BOM), list.line_ending())? {
+            match block {
+                Block::Stanza { line, entry, raw } => {
+                    let entries = entry.to_entries().map_err(|why| SourcesListError::BadLine {
+                        line,
+                        text: raw.lines().next().unwrap_or("").into(),
+                        why,
+                    })?;
+
+                    list.lines.extend(entries.into_iter().map(SourceLine::Entry));
+                }
+                Block::Comment(comment) => list.lines.push(SourceLine::Comment(comment.into())),
+                Block::Empty => list.lines.push(SourceLine::Empty),
+            }
+        }
+
+        Ok(list)
+    }
+
+    /// Writes the lines of a deb822 list, with the entries of each stanza that it was parsed
+    /// from written together as that stanza.
+    ///
+    /// If `preserve` is set, stanzas whose entries are unchanged are written exactly as they
+    /// were parsed, and the fields of a changed stanza are rewritten in place, so that a list is
+    /// only rewritten where it was changed. Entries which no stanza can describe are written as
+    /// stanzas of their own.
+    pub(crate) fn render_deb822(&self, fmt: &mut Formatter, preserve: bool) -> fmt::Result {
+        /// Whether the `entries` may be an edit of those of a stanza, which described the
+        /// `original` entries: either each entry kept its URL or its suite, or some were removed.
+        fn edit_of(original: &[SourceLine], entries: &[SourceEntry]) -> bool {
+            if original.len() == entries.len() {
+                original.iter().zip(entries).all(|(line, b)| match line {
+                    SourceLine::Entry(a) => a.url == b.url || a.suite == b.suite,
+                    _ => false,
+                })
+            } else {
+                part_of(original, entries)
+            }
+        }
+
+        /// Whether each of the `entries` has the type, URL, and suite of an `original` entry.
+        fn part_of(original: &[SourceLine], entries: &[SourceEntry]) -> bool {
+            entries.iter().all(|entry| {
+                original.iter().any(|line| match line {
+                    SourceLine::Entry(other) => entry_key(other) == entry_key(entry),
+                    _ => false,
+                })
+            })
+        }
+
+        let ending = self.line_ending();
+        let mut stanzas: Vec<(Deb822Entry, Vec<SourceLine>, String)> = Vec::new();
+        if preserve {
+            if let Some(ref original) = self.original {
+                for block in blocks(original.trim_start_matches(BOM), ending).unwrap_or_default() {
+                    if let Block::Stanza { entry, raw, .. } = block {
+                        if let Ok(entries) = entry.to_entries() {
+                            let lines = entries.into_iter().map(SourceLine::Entry).collect();
+                            stanzas.push((entry, lines, raw));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut used = vec![false; stanzas.len()];
+        let mut last = None;
+        let mut pos = 0;
+        let mut after_stanza = false;
+        while pos < self.lines.len() {
+            let line = &self.lines[pos];
+            // Anything written directly after a stanza would be read as a part of it.
+            if after_stanza && *line != SourceLine::Empty {
+                fmt.write_str(ending)?;
+            }
+
+            after_stanza = false;
+            let entry = match *line {
+                SourceLine::Entry(ref entry) => entry,
+                ref line => {
+                    write!(fmt, "{}{}", line, ending)?;
+                    last = None;
+                    pos += 1;
+                    continue;
+                }
+            };
+
+            let exact = (0..stanzas.len())
+                .find(|&id| !used[id] && self.lines[pos..].starts_with(&stanzas[id].1));
+
+            if let Some(id) = exact {
+                let (_, ref lines, ref raw) = stanzas[id];
+                fmt.write_str(raw)?;
+                pos += lines.len();
+                used[id] = true;
+                last = Some(id);
+                after_stanza = true;
+                continue;
+            }
+
+            // The entries up to the next line of another kind.
+            let run: Vec<SourceEntry> = self.lines[pos..]
+                .iter()
+                .scan((), |_, line| match line {
+                    SourceLine::Entry(entry) => Some(entry.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            let longest = |id: usize, related: fn(&[SourceLine], &[SourceEntry]) -> bool| {
+                let (ref stanza, ref lines, _) = stanzas[id];
+                (1..=run.len().min(lines.len())).rev().find_map(|len| {
+                    if !related(lines, &run[..len]) {
+                        return None;
+                    }
+
+                    stanza.updated(&run[..len]).map(|stanza| (id, stanza, len))
+                })
+            };
+
+            // The remainder of a stanza which was split is written in its form, without its
+            // comments, which stay with the first part.
+            let split = last.and_then(|id| longest(id, part_of)).map(|(id, mut stanza, len)| {
+                stanza.lines.retain(|line| match line {
+                    Deb822Line::Field { .. } => true,
+                    Deb822Line::Comment(_) => false,
+                });
+
+                (id, stanza, len)
+            });
+
+            let edited = split.or_else(|| {
+                (0..stanzas.len()).filter(|&id| !used[id]).find_map(|id| longest(id, edit_of))
+            });
+
+            match edited {
+                Some((id, stanza, len)) => {
+                    fmt.write_str(&stanza.to_string().replace('\n', ending))?;
+                    pos += len;
+                    used[id] = true;
+                    last = Some(id);
+                }
+                None => {
+                    fmt.write_str(&entry.to_deb822().replace('\n', ending))?;
+                    last = None;
+                    pos += 1;
+                }
+            }
+
+            after_stanza = true;
+        }
+
+        Ok(())
+    }
+}
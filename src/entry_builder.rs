@@ -0,0 +1,102 @@
+use super::*;
+use std::path::{Path, PathBuf};
+
+/// Builds a `SourceEntry` one field at a time, such as from the inputs of a form.
+///
+/// Entries are enabled, and are binary (`deb`) entries unless `source` is set.
+#[derive(Clone, Debug, Default)]
+pub struct SourceEntryBuilder {
+    url: Option<String>,
+    suite: Option<String>,
+    components: Vec<String>,
+    source: bool,
+    architectures: Vec<String>,
+    signed_by: Option<PathBuf>,
+}
+
+impl SourceEntryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the URL of the repository.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// Sets the suite, such as `disco` or `disco-updates`.
+    pub fn suite(mut self, suite: &str) -> Self {
+        self.suite = Some(suite.to_owned());
+        self
+    }
+
+    /// Adds a component, if it was not already added.
+    pub fn component(mut self, component: &str) -> Self {
+        if !self.components.iter().any(|c| c == component) {
+            self.components.push(component.to_owned());
+        }
+
+        self
+    }
+
+    /// Adds each of the components.
+    pub fn components(self, components: &[&str]) -> Self {
+        components.iter().fold(self, |builder, component| builder.component(component))
+    }
+
+    /// Sets whether the entry is for source packages (`deb-src`) or binaries (`deb`).
+    pub fn source(mut self, source: bool) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Adds an architecture to the `arch` option.
+    pub fn arch(mut self, arch: &str) -> Self {
+        if !self.architectures.iter().any(|a| a == arch) {
+            self.architectures.push(arch.to_owned());
+        }
+
+        self
+    }
+
+    /// Sets the keyring of the `signed-by` option.
+    pub fn signed_by<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.signed_by = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Creates the entry.
+    ///
+    /// Returns `SourceError::MissingField` if the URL or suite was not set, or if no component
+    /// was added for a suite which is not the path of a flat repository.
+    pub fn build(self) -> SourceResult<SourceEntry> {
+        let url = self.url.ok_or(SourceError::MissingField { field: "url" })?;
+        let suite = self.suite.ok_or(SourceError::MissingField { field: "suite" })?;
+        if self.components.is_empty() && !suite.ends_with('/') {
+            return Err(SourceError::MissingField { field: "components" });
+        }
+
+        let mut entry = SourceEntry {
+            enabled: true,
+            locked: false,
+            source: self.source,
+            options: None,
+            url,
+            suite,
+            components: self.components,
+            comment: None,
+            comment_spacing: None,
+        };
+
+        for arch in &self.architectures {
+            entry.add_architecture(arch);
+        }
+
+        if let Some(path) = self.signed_by {
+            entry.set_signed_by(path);
+        }
+
+        Ok(entry)
+    }
+}
@@ -1,5 +1,6 @@
 use std::io;
 use std::path::PathBuf;
+use std::str::Utf8Error;
 
 /// An error that may occur when parsing apt sources.
 #[derive(Debug, Error)]
@@ -20,12 +21,28 @@ pub enum SourceError {
     SourcesList { path: PathBuf, why: Box<SourcesListError> },
     #[error(display = "failed to open / read source list at {:?}: {}", path, why)]
     SourcesListOpen { path: PathBuf, why: io::Error },
+    #[error(display = "release file at {} is unreachable: {}", url, why)]
+    ReleaseUnreachable { url: String, why: String },
+    #[error(display = "include cycle detected at {:?}", path)]
+    IncludeCycle { path: PathBuf },
 }
 
 #[derive(Debug, Error)]
 pub enum SourcesListError {
-    #[error(display = "parsing error on line {}: {}", line, why)]
-    BadLine { line: usize, why: SourceError },
+    #[error(display = "parsing error on line {} ('{}'): {}", line, text, why)]
+    BadLine { line: usize, text: String, why: SourceError },
+    #[error(display = "invalid UTF-8 at byte {}: {}", offset, why)]
+    InvalidUtf8 { offset: usize, why: Utf8Error },
+}
+
+/// An error that may occur when determining the current release of the sources lists.
+#[derive(Debug, Eq, Error, PartialEq)]
+pub enum ReleaseError {
+    #[error(display = "there are no active official archive entries")]
+    NoOfficialEntries,
+    /// The official archive entries do not agree on a single release. The releases are sorted.
+    #[error(display = "official archive entries are of more than one release")]
+    Conflict { releases: Vec<String> },
 }
 
 impl From<io::Error> for SourceError {
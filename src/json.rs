@@ -0,0 +1,47 @@
+use super::*;
+use serde_json;
+use std::path::Path;
+
+/// A flattened record of an entry, including the path of the list that it belongs to.
+#[derive(Serialize)]
+struct EntryRecord<'a> {
+    path: &'a Path,
+    url: &'a str,
+    suite: &'a str,
+    components: &'a [String],
+    options: Option<&'a str>,
+    enabled: bool,
+    source: bool,
+}
+
+impl SourcesLists {
+    /// Exports each active entry as a JSON object on its own line.
+    ///
+    /// Requires the `json` feature.
+    pub fn to_jsonl(&self) -> String {
+        let mut output = String::new();
+        for list in self.iter() {
+            for line in &list.lines {
+                let entry = match line {
+                    SourceLine::Entry(entry) if entry.enabled => entry,
+                    _ => continue,
+                };
+
+                let record = EntryRecord {
+                    path: &list.path,
+                    url: &entry.url,
+                    suite: &entry.suite,
+                    components: &entry.components,
+                    options: entry.options.as_deref(),
+                    enabled: entry.enabled,
+                    source: entry.source,
+                };
+
+                output.push_str(&serde_json::to_string(&record).expect("entry is serializable"));
+                output.push('\n');
+            }
+        }
+
+        output
+    }
+}
@@ -8,16 +8,48 @@
 
 #[macro_use]
 extern crate err_derive;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "network")]
+extern crate ureq;
 
+mod annotations;
+mod apt_config;
+mod archive;
+mod changes;
+mod deb822;
+mod entry_builder;
 mod errors;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "network")]
+mod network;
+mod options;
+mod parse_options;
+mod plan;
 mod source_entry;
 mod source_line;
 mod sources_list;
+mod suite;
+mod validate;
 
 #[cfg(test)]
 mod tests;
 
+pub use self::apt_config::*;
+pub use self::archive::*;
+pub use self::changes::*;
+pub use self::deb822::*;
+pub use self::entry_builder::*;
 pub use self::errors::*;
+pub use self::options::*;
+pub use self::parse_options::*;
+pub use self::plan::*;
 pub use self::source_entry::*;
 pub use self::source_line::*;
 pub use self::sources_list::*;
+pub use self::suite::*;
+pub use self::validate::*;
@@ -0,0 +1,43 @@
+use super::*;
+
+impl SourceEntry {
+    /// Checks that the `InRelease` file of this entry can be fetched from its repository.
+    pub fn check_release(&self) -> SourceResult<()> {
+        let url = self.release_url();
+        match ureq::head(&url).call() {
+            Ok(_) => Ok(()),
+            Err(why) => Err(SourceError::ReleaseUnreachable { url, why: why.to_string() }),
+        }
+    }
+}
+
+impl SourcesLists {
+    /// Enables the entries of the `repo` on the `suite`, but only if their `InRelease` file can
+    /// be fetched.
+    ///
+    /// Returns `true` if any entry was enabled. If the release is unreachable, no entries are
+    /// changed and the reason is returned as an error. Locked entries are left disabled.
+    pub fn enable_if_valid(&mut self, repo: &str, suite: &str) -> SourceResult<bool> {
+        let entry = self
+            .entries()
+            .find(|entry| entry.url == repo && entry.suite == suite)
+            .ok_or(SourceError::EntryNotFound)?;
+
+        entry.check_release()?;
+
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let mut changed = false;
+        for (id, list) in files.iter_mut().enumerate() {
+            for entry in list.get_entries_mut(repo).filter(|entry| entry.suite == suite) {
+                if !entry.enabled && !entry.locked {
+                    entry.enabled = true;
+                    add_modified(modified, id as u16);
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+}
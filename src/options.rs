@@ -0,0 +1,425 @@
+use super::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Option keys which relate to apt pinning, rather than to fetching the repository.
+pub const PINNING_OPTIONS: &[&str] = &["target-release", "default-release", "pin", "pin-priority"];
+
+/// Boolean option keys which relax apt's verification of a repository when enabled.
+pub const SECURITY_OPTIONS: &[&str] =
+    &["allow-insecure", "allow-weak", "allow-downgrade-to-insecure"];
+
+/// Pairs of options which contradict each other when both are given.
+///
+/// `trusted=yes` disables the verification which a `signed-by` keyring would be used for.
+pub const CONFLICTING_OPTIONS: &[(&str, &str)] = &[("trusted", "signed-by")];
+
+/// Builds an options string for a `SourceEntry`, rejecting contradictory options.
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    options: Vec<(String, String)>,
+}
+
+impl OptionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `value` of an option, replacing any earlier value for the same `key`.
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        match self.options.iter_mut().find(|(k, _)| k == key) {
+            Some(option) => option.1 = value.to_owned(),
+            None => self.options.push((key.to_owned(), value.to_owned())),
+        }
+
+        self
+    }
+
+    /// Creates the options string, quoting values which contain whitespace.
+    ///
+    /// Returns `None` if no options were set, and an error if a pair of options from
+    /// `CONFLICTING_OPTIONS` are both given. Boolean options which are disabled do not conflict.
+    pub fn build(self) -> SourceResult<Option<String>> {
+        let given = |key: &str| {
+            self.options.iter().any(|(k, value)| k == key && parse_bool(value) != Some(false))
+        };
+
+        for &(a, b) in CONFLICTING_OPTIONS {
+            if given(a) && given(b) {
+                return Err(SourceError::InvalidValue {
+                    field: "options",
+                    value: [a, " conflicts with ", b].concat(),
+                });
+            }
+        }
+
+        if self.options.is_empty() {
+            return Ok(None);
+        }
+
+        let tokens: Vec<String> =
+            self.options.iter().map(|(key, value)| option_token(key, value)).collect();
+
+        Ok(Some(tokens.join(" ")))
+    }
+}
+
+/// Writes an option as `key=value`, quoting the value if it contains whitespace.
+pub(crate) fn option_token(key: &str, value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        [key, "=\"", value, "\""].concat()
+    } else {
+        [key, "=", value].concat()
+    }
+}
+
+/// Splits an options string on whitespace, except within double quotes.
+pub(crate) fn split_options(options: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+
+    for (pos, character) in options.char_indices() {
+        if character == '"' {
+            quoted = !quoted;
+        } else if character.is_whitespace() && !quoted {
+            if let Some(start) = start.take() {
+                tokens.push(&options[start..pos]);
+            }
+
+            continue;
+        }
+
+        if start.is_none() {
+            start = Some(pos);
+        }
+    }
+
+    if let Some(start) = start {
+        tokens.push(&options[start..]);
+    }
+
+    tokens
+}
+
+/// Splits an options string into its `key=value` pairs.
+///
+/// Options which lack a value are returned with an empty value. Quotes around a value are
+/// removed, so that `signed-by="/etc/apt/keyrings/my key.gpg"` has a value without quotes.
+pub(crate) fn option_pairs(options: &str) -> impl Iterator<Item = (&str, &str)> {
+    split_options(options).into_iter().map(|option| match option.find('=') {
+        Some(pos) => {
+            let value = &option[pos + 1..];
+            let value = if value.len() > 1 && value.starts_with('"') && value.ends_with('"') {
+                &value[1..value.len() - 1]
+            } else {
+                value
+            };
+
+            (&option[..pos], value)
+        }
+        None => (option, ""),
+    })
+}
+
+/// The keyring that an entry's `signed-by` option refers to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Signature {
+    /// A keyring file, or a path that could not be found on the system.
+    KeyringPath(PathBuf),
+    /// A directory of keyrings.
+    KeyringDir(PathBuf),
+    /// Several keyrings, separated by commas, any of which may sign the repository.
+    Keyrings(Vec<PathBuf>),
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signature::KeyringPath(path) | Signature::KeyringDir(path) => {
+                write!(fmt, "{}", path.display())
+            }
+            Signature::Keyrings(paths) => {
+                for (id, path) in paths.iter().enumerate() {
+                    if id != 0 {
+                        fmt.write_str(",")?;
+                    }
+
+                    write!(fmt, "{}", path.display())?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Merges two options strings, with the options of `new` replacing those of `old` which have
+/// the same key.
+pub(crate) fn merge_options(old: Option<&str>, new: Option<&str>) -> Option<String> {
+    let mut tokens: Vec<&str> = old.map(split_options).unwrap_or_default();
+    for token in new.into_iter().flat_map(split_options) {
+        let key = token.split('=').next().unwrap_or(token);
+        match tokens.iter().position(|old| old.split('=').next() == Some(key)) {
+            Some(pos) => tokens[pos] = token,
+            None => tokens.push(token),
+        }
+    }
+
+    if tokens.is_empty() {
+        None
+    } else {
+        Some(tokens.join(" "))
+    }
+}
+
+/// Interprets a boolean option value the way that apt does.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" | "true" | "on" | "enable" | "with" | "1" => Some(true),
+        "no" | "false" | "off" | "disable" | "without" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl Signature {
+    /// Creates a signature for the path, checking the system for whether it is a directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Signature::KeyringDir(path.to_path_buf())
+        } else {
+            Signature::KeyringPath(path.to_path_buf())
+        }
+    }
+
+    /// Creates a signature from the value of a `signed-by` option, which may list several
+    /// keyrings separated by commas.
+    pub fn from_value(value: &str) -> Self {
+        let paths: Vec<&str> = value.split(',').filter(|path| !path.is_empty()).collect();
+        match paths.len() {
+            0 => Signature::from_path(value),
+            1 => Signature::from_path(paths[0]),
+            _ => Signature::Keyrings(paths.into_iter().map(PathBuf::from).collect()),
+        }
+    }
+
+    /// The path of the keyring, or the first of several keyrings.
+    pub fn path(&self) -> &Path {
+        match self {
+            Signature::KeyringPath(path) | Signature::KeyringDir(path) => path,
+            Signature::Keyrings(paths) => &paths[0],
+        }
+    }
+}
+
+impl SourceEntry {
+    /// The option tokens exactly as they were written between the brackets, in their original
+    /// order.
+    ///
+    /// Tokens are split on whitespace, except within double quotes.
+    pub fn option_tokens(&self) -> Vec<&str> {
+        self.options.as_deref().map(split_options).unwrap_or_default()
+    }
+
+    /// Fetches the value of an option defined for this entry, such as `arch`.
+    ///
+    /// If the option is given more than once, the last value wins.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options
+            .as_ref()
+            .and_then(|options| option_pairs(options).filter(|&(k, _)| k == key).last())
+            .map(|(_, value)| value)
+    }
+
+    /// Keys of options which are given more than once.
+    pub fn duplicate_options(&self) -> Vec<&str> {
+        let mut seen = Vec::new();
+        let mut duplicates = Vec::new();
+        for (key, _) in self.options.as_deref().into_iter().flat_map(option_pairs) {
+            if seen.contains(&key) {
+                if !duplicates.contains(&key) {
+                    duplicates.push(key);
+                }
+            } else {
+                seen.push(key);
+            }
+        }
+
+        duplicates
+    }
+
+    /// Removes every option which is overridden by a later option of the same key.
+    ///
+    /// Returns `true` if any option was removed.
+    pub fn dedup_options(&mut self) -> bool {
+        let deduped = match self.options {
+            Some(ref options) => {
+                fn key(token: &str) -> &str {
+                    token.split('=').next().unwrap_or(token)
+                }
+
+                let tokens = split_options(options);
+                let kept: Vec<&str> = tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|&(pos, token)| {
+                        !tokens[pos + 1..].iter().any(|later| key(later) == key(token))
+                    })
+                    .map(|(_, token)| *token)
+                    .collect();
+
+                if kept.len() == tokens.len() {
+                    return false;
+                }
+
+                kept.join(" ")
+            }
+            None => return false,
+        };
+
+        self.options = Some(deduped);
+        true
+    }
+
+    /// Fetches the value of a boolean option, such as `allow-insecure`.
+    ///
+    /// Values which apt would not recognize as a boolean are returned as an error.
+    pub fn option_bool(&self, key: &str) -> Option<SourceResult<bool>> {
+        self.option(key).map(|value| {
+            parse_bool(value).ok_or_else(|| SourceError::InvalidValue {
+                field: "options",
+                value: [key, "=", value].concat(),
+            })
+        })
+    }
+
+    /// The security options from `SECURITY_OPTIONS` which are enabled for this entry.
+    pub fn security_relaxations(&self) -> Vec<&str> {
+        SECURITY_OPTIONS
+            .iter()
+            .cloned()
+            .filter(|key| match self.option_bool(key) {
+                Some(Ok(enabled)) => enabled,
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// Whether apt checks the `Valid-Until` field of the Release file, as set by the
+    /// `check-valid-until` option.
+    ///
+    /// Returns `None` if the option is not given, or if its value is not a boolean which apt
+    /// understands. Such values are kept as they are, and flagged by `SourcesLists::validate`.
+    pub fn check_valid_until(&self) -> Option<bool> {
+        match self.option_bool("check-valid-until") {
+            Some(Ok(check)) => Some(check),
+            _ => None,
+        }
+    }
+
+    /// The architectures which the `arch` option restricts this entry to.
+    ///
+    /// An empty list means that the architectures are not known, and apt will use its defaults.
+    pub fn architectures(&self) -> Vec<String> {
+        self.option("arch")
+            .map(|archs| {
+                archs.split(',').filter(|arch| !arch.is_empty()).map(String::from).collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The keyring defined by the `signed-by` option.
+    pub fn signature(&self) -> Option<Signature> {
+        self.option("signed-by").map(Signature::from_value)
+    }
+
+    /// The first keyring listed by the `signed-by` option.
+    pub fn signed_by(&self) -> Option<&Path> {
+        self.option("signed-by")?.split(',').find(|path| !path.is_empty()).map(Path::new)
+    }
+
+    /// Every keyring listed by the `signed-by` option, which separates them with commas.
+    pub fn signed_by_all(&self) -> Vec<PathBuf> {
+        self.option("signed-by")
+            .map(|value| {
+                value.split(',').filter(|path| !path.is_empty()).map(PathBuf::from).collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sets the keyring of the `signed-by` option, replacing any keyrings that it listed.
+    ///
+    /// Other options keep their order, and an existing `signed-by` option keeps its place.
+    pub fn set_signed_by<P: AsRef<Path>>(&mut self, path: P) {
+        self.replace_option("signed-by", Some(&path.as_ref().to_string_lossy()));
+    }
+
+    /// Adds an architecture to the `arch` option, if it is not already listed.
+    ///
+    /// Returns `true` if the architecture was added.
+    pub fn add_architecture(&mut self, arch: &str) -> bool {
+        let mut archs = self.architectures();
+        if archs.iter().any(|existing| existing == arch) {
+            return false;
+        }
+
+        archs.push(arch.to_owned());
+        let archs = archs.join(",");
+        self.replace_option("arch", Some(&archs));
+        true
+    }
+
+    /// Removes an architecture from the `arch` option, removing the option when none remain.
+    ///
+    /// Returns `true` if the architecture was listed.
+    pub fn remove_architecture(&mut self, arch: &str) -> bool {
+        let mut archs = self.architectures();
+        let len = archs.len();
+        archs.retain(|existing| existing != arch);
+        if archs.len() == len {
+            return false;
+        }
+
+        let archs = archs.join(",");
+        self.replace_option("arch", if archs.is_empty() { None } else { Some(&archs) });
+        true
+    }
+
+    /// Replaces every option of the `key` with a single option in the place of the first, or
+    /// removes them if no value is given.
+    fn replace_option(&mut self, key: &str, value: Option<&str>) {
+        let token = value.map(|value| option_token(key, value));
+        let mut tokens = Vec::new();
+        let mut replaced = false;
+        for existing in self.option_tokens() {
+            if existing.split('=').next() != Some(key) {
+                tokens.push(existing);
+            } else if !replaced {
+                tokens.extend(token.as_deref());
+                replaced = true;
+            }
+        }
+
+        if !replaced {
+            tokens.extend(token.as_deref());
+        }
+
+        self.options = if tokens.is_empty() { None } else { Some(tokens.join(" ")) };
+    }
+
+    /// The release which the `target-release` or `default-release` option pins this entry to.
+    pub fn target_release(&self) -> Option<&str> {
+        self.option("target-release").or_else(|| self.option("default-release"))
+    }
+
+    /// Options which carry pinning hints, to be correlated with apt's preferences.
+    pub fn pin_hints(&self) -> Vec<(&str, &str)> {
+        self.options
+            .as_ref()
+            .map(|options| {
+                option_pairs(options).filter(|&(key, _)| PINNING_OPTIONS.contains(&key)).collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,37 @@
+/// Options which control how a `SourcesList` is parsed.
+///
+/// The defaults match apt's own interpretation of a sources list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseOptions {
+    /// Recognize `# include: <path>` comments as `SourceLine::Include` directives.
+    pub includes: bool,
+    /// Silently drop lines which duplicate an earlier line of the same list.
+    ///
+    /// This is enabled by default. When disabled, duplicates can be found with
+    /// `SourcesList::duplicate_lines`.
+    pub dedup: bool,
+    /// Accept entries with `${...}` placeholders even when they lack components, as a
+    /// placeholder may expand to several fields. See `SourceEntry::is_templated`.
+    pub templates: bool,
+    /// Recognize entries disabled with this marker, such as the `LOCK_MARKER`, as locked rather
+    /// than as comments. See `SourceEntry::locked`.
+    ///
+    /// The marker is kept by the parsed `SourcesList`, so that its locked entries are written
+    /// back with the same marker.
+    pub locks: Option<String>,
+    /// Reject entries whose URL does not have one of the `URL_SCHEMES`, rather than leaving
+    /// the mistake to be found by `apt update`.
+    pub strict_urls: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            includes: false,
+            dedup: true,
+            templates: false,
+            locks: None,
+            strict_urls: false,
+        }
+    }
+}
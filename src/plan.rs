@@ -0,0 +1,162 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// A change to a single list, which `SourcesLists::apply` will make.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PlannedChange {
+    /// The entry will be appended to the list.
+    Insert(SourceEntry),
+    /// The entry will be deleted from the list.
+    Remove(SourceEntry),
+    /// The disabled entry will be enabled.
+    Enable(SourceEntry),
+}
+
+impl Display for PlannedChange {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            PlannedChange::Insert(ref entry) => write!(fmt, "insert: {}", entry),
+            PlannedChange::Remove(ref entry) => write!(fmt, "remove: {}", entry),
+            PlannedChange::Enable(ref entry) => write!(fmt, "enable: {}", entry),
+        }
+    }
+}
+
+/// The changes which would make the lists match a desired set of entries, grouped by the path
+/// of the list that each change applies to.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Plan {
+    pub changes: BTreeMap<PathBuf, Vec<PlannedChange>>,
+}
+
+impl Plan {
+    /// Whether the lists already match the desired entries.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn push(&mut self, path: &Path, change: PlannedChange) {
+        self.changes.entry(path.to_path_buf()).or_default().push(change);
+    }
+}
+
+impl SourcesLists {
+    /// Computes the changes which would leave exactly the `desired` entries active.
+    ///
+    /// Desired entries are treated as enabled, and compared as by `semantically_eq`. A desired
+    /// entry which is disabled in a list is enabled there, and one which is missing is inserted
+    /// into the first list with an entry of the same URL, or else where `place_entry` would
+    /// put it. Active entries which are not desired are removed.
+    ///
+    /// Nothing is changed until the plan is given to `apply`.
+    pub fn plan(&self, desired: &[SourceEntry]) -> Plan {
+        self.plan_with(&AptConfig::standard(), desired)
+    }
+
+    /// Computes the plan as `plan` does, placing new lists within the locations given by apt's
+    /// configuration.
+    pub fn plan_with(&self, conf: &AptConfig, desired: &[SourceEntry]) -> Plan {
+        let mut plan = Plan::default();
+        let desired_keys: Vec<_> = desired.iter().map(semantic_key).collect();
+
+        for (entry, key) in desired.iter().zip(&desired_keys) {
+            let matching = || {
+                self.iter().flat_map(|list| {
+                    list.lines.iter().filter_map(move |line| match line {
+                        SourceLine::Entry(current) if semantic_key(current) == *key => {
+                            Some((list.path.as_path(), current))
+                        }
+                        _ => None,
+                    })
+                })
+            };
+
+            if matching().any(|(_, current)| current.enabled) {
+                continue;
+            }
+
+            if let Some((path, current)) = matching().find(|(_, c)| !c.locked) {
+                plan.push(path, PlannedChange::Enable(current.clone()));
+                continue;
+            }
+
+            let entry = SourceEntry { enabled: true, locked: false, ..entry.clone() };
+            let has_url = |list: &&SourcesList| {
+                list.lines.iter().any(|line| match line {
+                    SourceLine::Entry(current) => current.url() == entry.url(),
+                    _ => false,
+                })
+            };
+
+            let path = match (self.iter().find(has_url), entry.list_filename()) {
+                (Some(list), _) => list.path.clone(),
+                (None, Some(filename)) => conf.sourceparts.join(filename),
+                (None, None) => conf.sourcelist.clone(),
+            };
+
+            plan.push(&path, PlannedChange::Insert(entry));
+        }
+
+        for list in self.iter() {
+            for line in &list.lines {
+                if let SourceLine::Entry(ref current) = *line {
+                    if current.enabled && !desired_keys.contains(&semantic_key(current)) {
+                        plan.push(&list.path, PlannedChange::Remove(current.clone()));
+                    }
+                }
+            }
+        }
+
+        plan
+    }
+
+    /// Makes the changes of a `plan`, marking each list which was changed as modified.
+    ///
+    /// If an entry to be removed or enabled is no longer in its list, as the lists were changed
+    /// after the plan was made, `SourceError::EntryNotFound` is returned. Changes to lists which
+    /// come before that list are kept.
+    pub fn apply(&mut self, plan: Plan) -> SourceResult<()> {
+        for (path, changes) in plan.changes {
+            let id = match self.iter().position(|list| list.path == path) {
+                Some(id) => id,
+                None => {
+                    self.files.push(SourcesList::from_lines(&path, Vec::new()));
+                    self.files.len() - 1
+                }
+            };
+
+            add_modified(&mut self.modified, id as u16);
+            let list = &mut self.files[id];
+
+            for change in changes {
+                match change {
+                    PlannedChange::Insert(entry) => list.lines.push(SourceLine::Entry(entry)),
+                    PlannedChange::Remove(entry) => {
+                        let pos = position_of(list, &entry)?;
+                        list.lines.remove(pos);
+                    }
+                    PlannedChange::Enable(entry) => {
+                        let pos = position_of(list, &entry)?;
+                        if let SourceLine::Entry(ref mut entry) = list.lines[pos] {
+                            entry.enabled = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn position_of(list: &SourcesList, entry: &SourceEntry) -> SourceResult<usize> {
+    list.lines
+        .iter()
+        .position(|line| match line {
+            SourceLine::Entry(current) => current == entry,
+            _ => false,
+        })
+        .ok_or(SourceError::EntryNotFound)
+}
@@ -1,12 +1,39 @@
 use super::*;
+use std::cmp::Ordering;
 use std::fmt;
+use std::iter;
 use std::str::FromStr;
 
+/// Hosts which are known to serve their archives over HTTPS.
+///
+/// Only these exact hosts match, as their subdomains, such as the country mirrors of
+/// `archive.ubuntu.com`, are run by third parties which may not support HTTPS.
+pub const HTTPS_HOSTS: &[&str] = &[
+    "archive.ubuntu.com",
+    "security.ubuntu.com",
+    "ppa.launchpad.net",
+    "deb.debian.org",
+    "security.debian.org",
+    "apt.pop-os.org",
+];
+
+/// The URL schemes which apt can fetch from, as accepted by `ParseOptions::strict_urls`.
+///
+/// Each may also be wrapped by one of the `URL_SCHEME_WRAPPERS`, such as `mirror+http`.
+pub const URL_SCHEMES: &[&str] = &["http", "https", "ftp", "file", "cdrom", "mirror"];
+
+/// The methods which fetch through another method, given as `<wrapper>+<scheme>`.
+pub const URL_SCHEME_WRAPPERS: &[&str] = &["mirror", "tor"];
+
 /// An apt source entry that is active on the system.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SourceEntry {
     /// Whether the entry is enabled or not.
     pub enabled: bool,
+    /// Whether the entry was disabled with the lock marker of `ParseOptions::locks`, so that
+    /// operations which enable entries in bulk will leave it disabled.
+    pub locked: bool,
     /// Whether this is a binary or source repo.
     pub source: bool,
     /// Some repos may have special options defined.
@@ -17,12 +44,17 @@ pub struct SourceEntry {
     pub suite: String,
     /// Components that have been enabled for this repo.
     pub components: Vec<String>,
+    /// The text of a comment which trails the entry on its line, after the `#`.
+    pub comment: Option<String>,
+    /// The whitespace between the `#` of the `comment` and its text, as written, if it was not
+    /// the single space which is written by default.
+    pub comment_spacing: Option<String>,
 }
 
 impl fmt::Display for SourceEntry {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         if !self.enabled {
-            fmt.write_str("# ")?;
+            fmt.write_str(if self.locked { "## " } else { "# " })?;
         }
 
         fmt.write_str(if self.source { "deb-src " } else { "deb " })?;
@@ -30,17 +62,86 @@ impl fmt::Display for SourceEntry {
             write!(fmt, "[{}] ", options)?;
         }
 
-        write!(fmt, "{} {} {}", self.url, self.suite, self.components.join(" "))
+        write!(fmt, "{} {}", self.url, self.suite)?;
+        if !self.components.is_empty() {
+            write!(fmt, " {}", self.components_str())?;
+        }
+
+        match self.comment.as_ref() {
+            Some(comment) if comment.is_empty() => fmt.write_str(" #"),
+            Some(comment) => {
+                let spacing = self.comment_spacing.as_deref().unwrap_or(" ");
+                write!(fmt, " #{}{}", spacing, comment)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Entries are ordered by their URL, suite, and whether they are binary or source entries, with
+/// the remaining fields only breaking ties.
+impl Ord for SourceEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (
+            &self.url,
+            &self.suite,
+            self.source,
+            &self.components,
+            &self.options,
+            self.enabled,
+            self.locked,
+        )
+            .cmp(&(
+                &other.url,
+                &other.suite,
+                other.source,
+                &other.components,
+                &other.options,
+                other.enabled,
+                other.locked,
+            ))
+            .then_with(|| self.comment.cmp(&other.comment))
+            .then_with(|| self.comment_spacing.cmp(&other.comment_spacing))
+    }
+}
+
+impl PartialOrd for SourceEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl FromStr for SourceEntry {
     type Err = SourceError;
     fn from_str(line: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(line, &ParseOptions::default())
+    }
+}
+
+impl SourceEntry {
+    /// Parses an entry, rejecting a URL which does not have one of the `URL_SCHEMES`.
+    ///
+    /// Parsing with `from_str` accepts any URL, as apt may be extended with other methods.
+    pub fn from_str_validated(line: &str) -> SourceResult<Self> {
+        Self::parse_with(line, &ParseOptions { strict_urls: true, ..ParseOptions::default() })
+    }
+
+    /// Parses an entry, with the given `parse_options` controlling how it is interpreted.
+    pub fn parse_with(line: &str, parse_options: &ParseOptions) -> SourceResult<Self> {
         let mut components = Vec::new();
         let mut options = None;
-        let url;
+        let mut url: String;
 
+        let (line, comment) = split_comment(line);
+        let (comment, comment_spacing) = match comment {
+            Some(comment) => {
+                let text = comment.trim();
+                let spacing = &comment[..comment.len() - comment.trim_start().len()];
+                let spacing = if spacing == " " { None } else { Some(spacing.to_owned()) };
+                (Some(text.to_owned()), spacing)
+            }
+            None => (None, None),
+        };
         let mut fields = line.split_whitespace();
 
         let source = match fields.next().ok_or(SourceError::MissingField { field: "source" })? {
@@ -92,21 +193,151 @@ impl FromStr for SourceEntry {
             url = field.into();
         }
 
+        // The media label of a `cdrom:[...]/` URL may contain whitespace, which is kept as is.
+        if url.starts_with("cdrom:[") && !url.contains(']') {
+            let start = line.find("cdrom:[").unwrap_or(0);
+            let rest = &line[start..];
+            let close = rest.find(']').ok_or(SourceError::MissingField { field: "url" })?;
+            let end = rest[close..].find(char::is_whitespace).map_or(rest.len(), |pos| close + pos);
+
+            url = rest[..end].to_owned();
+            fields = rest[end..].split_whitespace();
+        }
+
         if options.as_ref().map_or(false, String::is_empty) {
             options = None;
         }
 
-        let suite = fields.next().ok_or(SourceError::MissingField { field: "suite" })?.into();
+        let suite: String =
+            fields.next().ok_or(SourceError::MissingField { field: "suite" })?.into();
 
         for field in fields {
             components.push(field.into());
         }
 
-        Ok(SourceEntry { enabled: true, source, url, suite, components, options })
+        let entry = SourceEntry {
+            enabled: true,
+            locked: false,
+            source,
+            url,
+            suite,
+            components,
+            options,
+            comment,
+            comment_spacing,
+        };
+
+        // Only flat repositories, whose suite is a path such as `./`, may lack components.
+        if entry.components.is_empty()
+            && !entry.suite.ends_with('/')
+            && !(parse_options.templates && entry.is_templated())
+        {
+            return Err(SourceError::MissingField { field: "components" });
+        }
+
+        if parse_options.strict_urls && !valid_scheme(entry.scheme()) {
+            return Err(SourceError::InvalidValue { field: "url", value: entry.url });
+        }
+
+        Ok(entry)
+    }
+
+    /// Whether any field of this entry contains a `${...}` placeholder, left by a configuration
+    /// management tool which templates the list.
+    pub fn is_templated(&self) -> bool {
+        iter::once(&self.url)
+            .chain(iter::once(&self.suite))
+            .chain(self.options.iter())
+            .chain(self.components.iter())
+            .any(|field| match field.find("${") {
+                Some(start) => field[start..].contains('}'),
+                None => false,
+            })
+    }
+
+    /// Returns a copy of this entry which points to a different suite.
+    ///
+    /// Useful for setting up a parallel suite, such as `disco-backports` from `disco`.
+    pub fn with_suite(&self, suite: &str) -> SourceEntry {
+        SourceEntry { suite: suite.to_owned(), ..self.clone() }
+    }
+
+    /// Splits this entry into one entry per component, each with the same options and suite.
+    ///
+    /// An entry without components, such as that of a flat repository, is returned as is.
+    pub fn split_components(&self) -> Vec<SourceEntry> {
+        if self.components.is_empty() {
+            return vec![self.clone()];
+        }
+
+        self.components
+            .iter()
+            .map(|component| SourceEntry { components: vec![component.clone()], ..self.clone() })
+            .collect()
+    }
+
+    /// Returns the `deb-src` counterpart of this entry, with the same options, suite, and
+    /// components.
+    pub fn as_source(&self) -> SourceEntry {
+        SourceEntry { source: true, ..self.clone() }
+    }
+
+    /// Returns the `deb` counterpart of this entry, with the same options, suite, and
+    /// components.
+    pub fn as_binary(&self) -> SourceEntry {
+        SourceEntry { source: false, ..self.clone() }
+    }
+
+    /// Sets whether this entry is for source packages (`deb-src`) or binaries (`deb`).
+    pub fn set_source(&mut self, source: bool) {
+        self.source = source;
+    }
+
+    /// The components of this entry, joined as apt would write them.
+    pub fn components_str(&self) -> String {
+        self.components.join(" ")
+    }
+
+    /// Appends a component to this entry, returning `false` if it was already present.
+    pub fn add_component(&mut self, component: &str) -> bool {
+        if self.components.iter().any(|c| c == component) {
+            return false;
+        }
+
+        self.components.push(component.to_owned());
+        true
+    }
+
+    /// Replaces the components with those found in a whitespace-separated string.
+    pub fn set_components_str(&mut self, components: &str) {
+        self.components = components.split_whitespace().map(String::from).collect();
+    }
+
+    /// The base release of the suite, such as `disco` for `disco-security`.
+    pub fn release(&self) -> &str {
+        split_suite(&self.suite).0
+    }
+
+    /// The pocket of the suite, such as `security` for `disco-security`.
+    pub fn pocket(&self) -> Option<&str> {
+        split_suite(&self.suite).1
+    }
+
+    /// Whether this entry's suite is any of the given `suites`.
+    pub fn matches_any_suite(&self, suites: &[&str]) -> bool {
+        suites.iter().any(|suite| self.suite == *suite)
+    }
+
+    /// Whether this entry is on the given `release`, in any of its pockets.
+    pub fn matches_release(&self, release: &str) -> bool {
+        self.release() == release
+    }
+
+    /// The suite of this entry as a validated `Suite`.
+    pub fn suite_typed(&self) -> SourceResult<Suite> {
+        self.suite.parse::<Suite>()
     }
-}
 
-impl SourceEntry {
     pub fn url(&self) -> &str {
         let mut url: &str = &self.url;
         while url.ends_with('/') {
@@ -116,6 +347,69 @@ impl SourceEntry {
         url
     }
 
+    /// The scheme of the repo's URL, such as `http`, `https`, `file`, or `cdrom`.
+    ///
+    /// Returns an empty string if the URL has no scheme.
+    pub fn scheme(&self) -> &str {
+        match self.url.find(':') {
+            Some(pos) if !self.url[..pos].contains('/') => &self.url[..pos],
+            _ => "",
+        }
+    }
+
+    /// The URL without trailing slashes, and with repeated slashes in the path of a `file:` URL
+    /// collapsed, from which the dist and pool paths are built.
+    fn base_url(&self) -> String {
+        let url = self.url();
+        if self.scheme() != "file" {
+            return url.to_owned();
+        }
+
+        let path = &url["file:".len()..];
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        if path.starts_with('/') {
+            ["file:///", &parts.join("/")].concat()
+        } else {
+            ["file:", &parts.join("/")].concat()
+        }
+    }
+
+    /// The host name of the repo's URL, if it has one.
+    pub fn host(&self) -> Option<&str> {
+        let url = self.url();
+        let start = url.find("://")? + 3;
+        let host = &url[start..];
+        let host = host.find('/').map_or(host, |end| &host[..end]);
+        if host.is_empty() {
+            None
+        } else {
+            Some(host)
+        }
+    }
+
+    /// An origin identifier in the form of `<host> <suite>`, for mapping the output of
+    /// `apt-cache policy` back to this entry.
+    ///
+    /// URLs without a host, such as `file:` repositories, use the whole URL in place of the host.
+    pub fn origin_label(&self) -> String {
+        [self.host().unwrap_or_else(|| self.url()), " ", &self.suite].concat()
+    }
+
+    /// Returns a copy of this entry which uses `https`, if it uses `http` for a host that is
+    /// known to support HTTPS.
+    pub fn https_upgrade_candidate(&self) -> Option<SourceEntry> {
+        if !self.url.starts_with("http://") {
+            return None;
+        }
+
+        let host = self.host()?;
+        if !HTTPS_HOSTS.contains(&host) {
+            return None;
+        }
+
+        Some(SourceEntry { url: ["https://", &self.url[7..]].concat(), ..self.clone() })
+    }
+
     /// The base filename to be used when storing files for this entries.
     pub fn filename(&self) -> String {
         let mut url = self.url();
@@ -126,8 +420,18 @@ impl SourceEntry {
         url.replace("/", "_")
     }
 
+    /// Whether this is a flat repository, whose suite is a path such as `./`.
+    ///
+    /// Flat repositories have no components, and their indexes are found directly within the
+    /// suite's path rather than in a `dists` directory.
+    pub fn is_flat(&self) -> bool {
+        self.suite.ends_with('/')
+    }
+
     /// Returns the root URL for this entry's dist path.
     ///
+    /// For a flat repository, this is the suite's path within the URL.
+    ///
     /// For an entry such as:
     ///
     /// ```toml
@@ -140,20 +444,90 @@ impl SourceEntry {
     /// http://us.archive.ubuntu.com/ubuntu/dists/cosmic
     /// ```
     pub fn dist_path(&self) -> String {
-        [self.url(), "/dists/", &self.suite].concat()
+        let url = self.base_url();
+        if self.is_flat() {
+            let suite = self.suite.trim_start_matches("./").trim_matches('/');
+            if suite.is_empty() {
+                return url;
+            }
+
+            return [&url, "/", suite].concat();
+        }
+
+        [&url, "/dists/", &self.suite].concat()
     }
 
     pub fn dist_path_get(&self, path: &str) -> String {
+        [&self.dist_path(), "/", path].concat()
+    }
+
+    /// The URL of the `InRelease` file for this entry.
+    pub fn release_url(&self) -> String {
+        self.dist_path_get("InRelease")
+    }
+
+    /// The URLs of each index file that apt fetches for this entry.
+    ///
+    /// `Packages` indexes are only listed for the architectures given by the `arch` option,
+    /// as the architectures which apt defaults to are not known here.
+    pub fn index_urls(&self) -> Vec<String> {
+        let mut urls = vec![self.release_url()];
+
+        if self.is_flat() {
+            urls.push(self.dist_path_get(if self.source { "Sources" } else { "Packages" }));
+            return urls;
+        }
+
+        let architectures = self.architectures();
+        for component in self.dist_components() {
+            if self.source {
+                urls.push([&component, "/source/Sources"].concat());
+            } else {
+                for arch in &architectures {
+                    urls.push([&component, "/binary-", arch, "/Packages"].concat());
+                }
+            }
+        }
+
+        urls
+    }
+
+    /// The identifiers which apt uses for each index of this entry in its output, such as
+    /// `http://archive.ubuntu.com/ubuntu disco/main amd64 Packages`.
+    ///
+    /// These correspond to the files of `index_urls`, and so `Packages` identifiers are also
+    /// only given for the architectures of the `arch` option.
+    pub fn index_entries(&self) -> Vec<String> {
         let url = self.url();
-        [url, "/dists/", &self.suite, "/", path].concat()
+        let mut entries = vec![[url, " ", &self.suite, " InRelease"].concat()];
+
+        if self.is_flat() {
+            let index = if self.source { " Sources" } else { " Packages" };
+            entries.push([url, " ", &self.suite, index].concat());
+            return entries;
+        }
+
+        let architectures = self.architectures();
+        for component in &self.components {
+            let prefix = [url, " ", &self.suite, "/", component].concat();
+            if self.source {
+                entries.push([&prefix, " Sources"].concat());
+            } else {
+                for arch in &architectures {
+                    entries.push([&prefix, " ", arch, " Packages"].concat());
+                }
+            }
+        }
+
+        entries
     }
 
     /// Iterator that returns each of the dist components that are to be fetched.
     pub fn dist_components<'a>(&'a self) -> impl Iterator<Item = String> + 'a {
-        let url = self.url();
+        let url = self.base_url();
         self.components
             .iter()
-            .map(move |component| [url, "/dists/", &self.suite, "/", &component].concat())
+            .map(move |component| [&url, "/dists/", &self.suite, "/", &component].concat())
     }
 
     /// Returns the root URL for this entry's pool path.
@@ -170,6 +544,42 @@ impl SourceEntry {
     /// http://us.archive.ubuntu.com/ubuntu/pool/cosmic
     /// ```
     pub fn pool_path(&self) -> String {
-        [self.url(), "/pool/"].concat()
+        [&self.base_url(), "/pool/"].concat()
     }
 }
+
+/// Whether the `host` is the `domain`, or one of its subdomains.
+pub(crate) fn host_in_domain(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+/// Whether the scheme is one of the `URL_SCHEMES`, after unwrapping any of the
+/// `URL_SCHEME_WRAPPERS`.
+fn valid_scheme(scheme: &str) -> bool {
+    match scheme.find('+') {
+        Some(pos) => {
+            URL_SCHEME_WRAPPERS.contains(&&scheme[..pos]) && valid_scheme(&scheme[pos + 1..])
+        }
+        None => URL_SCHEMES.contains(&scheme),
+    }
+}
+
+/// Splits a comment which trails an entry from its line, returning the comment after its `#`.
+///
+/// Only a `#` which follows whitespace, and is outside of quotes, begins a comment. Components
+/// cannot begin with a `#`, so a component is never mistaken for a comment.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut after_space = false;
+    for (pos, character) in line.char_indices() {
+        if character == '"' {
+            quoted = !quoted;
+        } else if character == '#' && after_space && !quoted {
+            return (&line[..pos], Some(&line[pos + 1..]));
+        }
+
+        after_space = character.is_whitespace();
+    }
+
+    (line, None)
+}
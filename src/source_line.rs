@@ -1,13 +1,34 @@
 use super::*;
 use std::fmt;
+use std::path::PathBuf;
 use std::str::FromStr;
 
+/// The prefix of an entry which was disabled deliberately, and should not be re-enabled by
+/// automation.
+///
+/// Locked entries are displayed with this marker. A list which was parsed with another marker
+/// writes its locked entries back with that marker instead.
+pub const LOCK_MARKER: &str = "##";
+
+/// The prefix of a comment which `SourcesLists::insert_entry_with_comment` wrote above an entry,
+/// so that it can be recognized and replaced when the entry is inserted again.
+pub const COMMENT_MARKER: &str = "#:";
+
 /// A line from an apt source list.
+///
+/// With the `serde` feature, lines are serialized with their variant in a `type` field, and
+/// the contents of the variant in a `value` field.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum SourceLine {
     Comment(String),
     Empty,
     Entry(SourceEntry),
+    /// An `# include: <path>` directive, only recognized when enabled by `ParseOptions`.
+    Include(PathBuf),
+    /// A line which could not be parsed, kept verbatim.
+    Invalid(String),
 }
 
 impl fmt::Display for SourceLine {
@@ -16,6 +37,8 @@ impl fmt::Display for SourceLine {
             SourceLine::Comment(ref comment) => write!(fmt, "{}", comment),
             SourceLine::Empty => Ok(()),
             SourceLine::Entry(ref entry) => write!(fmt, "{}", entry),
+            SourceLine::Include(ref path) => write!(fmt, "# include: {}", path.display()),
+            SourceLine::Invalid(ref line) => write!(fmt, "{}", line),
         }
     }
 }
@@ -23,10 +46,35 @@ impl fmt::Display for SourceLine {
 impl FromStr for SourceLine {
     type Err = SourceError;
     fn from_str(line: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(line, &ParseOptions::default())
+    }
+}
+
+impl SourceLine {
+    /// Parses a line, with the given `options` controlling how it is interpreted.
+    pub fn parse_with(line: &str, options: &ParseOptions) -> Result<Self, SourceError> {
         let line = line.trim();
+        if options.includes {
+            if let Some(path) = include_directive(line) {
+                return Ok(SourceLine::Include(PathBuf::from(path)));
+            }
+        }
+
+        if let Some(ref marker) = options.locks {
+            if !marker.is_empty() && line.starts_with(marker.as_str()) {
+                let inner = line[marker.len()..].trim();
+                if let Ok(mut entry) = SourceEntry::parse_with(inner, options) {
+                    entry.enabled = false;
+                    entry.locked = true;
+                    return Ok(SourceLine::Entry(entry));
+                }
+            }
+        }
+
         if line.starts_with('#') {
             let inner = line[1..].trim();
-            let entry = if !inner.is_empty() { line.parse::<SourceEntry>().ok() } else { None };
+            let entry =
+                if !inner.is_empty() { SourceEntry::parse_with(inner, options).ok() } else { None };
 
             Ok(entry.map_or_else(
                 || SourceLine::Comment(line.into()),
@@ -38,7 +86,27 @@ impl FromStr for SourceLine {
         } else if line.is_empty() {
             Ok(SourceLine::Empty)
         } else {
-            Ok(SourceLine::Entry(line.parse::<SourceEntry>()?))
+            Ok(SourceLine::Entry(SourceEntry::parse_with(line, options)?))
         }
     }
 }
+
+/// Returns the path referenced by an `# include: <path>` comment, if the line is one.
+pub(crate) fn include_directive(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if !line.starts_with('#') {
+        return None;
+    }
+
+    let inner = line[1..].trim_start();
+    if !inner.starts_with("include:") {
+        return None;
+    }
+
+    let path = inner["include:".len()..].trim();
+    if path.is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
@@ -1,50 +1,206 @@
 use super::*;
-use std::collections::HashSet;
+use annotations::{entry_key, EntryKey};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt::{self, Display, Formatter};
 use std::fs::{self, File};
-use std::io::{self, Write};
-use std::ops::{Deref, DerefMut};
-use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::{Deref, DerefMut, Index};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::str::{self, FromStr};
+use std::time::SystemTime;
 
+/// The UTF-8 byte order mark which some editors write at the beginning of a file.
+pub(crate) const BOM: char = '\u{feff}';
+
+/// An apt sources list, and the lines that it contains.
+///
+/// Lists are created by parsing them, or with `SourcesList::from_lines`. They cannot be built
+/// from their fields alone, as the text that a list was parsed from is kept privately.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SourcesList {
     pub path: PathBuf,
     pub lines: Vec<SourceLine>,
+    /// Whether Windows line endings (`\r\n`) were detected when parsing, to be used when
+    /// writing the list back out.
+    pub crlf: bool,
+    /// Whether the list began with a UTF-8 byte order mark, to be written back out with it.
+    pub bom: bool,
+    /// Whether the list is in the deb822 format of `.sources` files, rather than the one-line
+    /// format, to be written back out in the same format.
+    pub deb822: bool,
+    /// The text that the list was parsed from, or last written as.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) original: Option<String>,
+    /// The marker that locked entries were parsed with, to be written back in place of the
+    /// `LOCK_MARKER`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) lock_marker: Option<String>,
 }
 
 impl FromStr for SourcesList {
     type Err = SourcesListError;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut source_list = Self::default();
-        for (no, line) in input.lines().enumerate() {
-            let entry = line
-                .parse::<SourceLine>()
-                .map_err(|why| SourcesListError::BadLine { line: no, why })?;
+        Self::parse_with(input, &ParseOptions::default())
+    }
+}
+
+impl SourcesList {
+    /// Parses a list, with the given `options` controlling how lines are interpreted.
+    pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Self, SourcesListError> {
+        let mut source_list = SourcesList {
+            crlf: input.contains("\r\n"),
+            bom: input.starts_with(BOM),
+            original: Some(input.to_owned()),
+            lock_marker: options.locks.clone(),
+            ..Self::default()
+        };
+        for (no, line) in input.trim_start_matches(BOM).lines().enumerate() {
+            let entry = SourceLine::parse_with(line, options)
+                .map_err(|why| SourcesListError::BadLine { line: no, text: line.into(), why })?;
 
             // Prevent duplicate entries.
-            if !source_list.lines.contains(&entry) {
+            if !options.dedup || !source_list.lines.contains(&entry) {
                 source_list.lines.push(entry);
             }
         }
 
         Ok(source_list)
     }
+
+    /// The indexes of lines which duplicate an earlier line of this list, ignoring empty lines.
+    ///
+    /// Duplicates are only retained when parsed without `ParseOptions::dedup`.
+    pub fn duplicate_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|&(pos, line)| *line != SourceLine::Empty && self.lines[..pos].contains(line))
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+}
+
+impl SourcesList {
+    /// Parses every line that can be parsed, rather than stopping at the first bad line.
+    ///
+    /// Each line which fails to parse is kept as a `SourceLine::Invalid`, and its error is
+    /// collected, so that every problem in the list can be reported at once.
+    pub fn parse_collecting(input: &str) -> (Self, Vec<SourcesListError>) {
+        let mut errors = Vec::new();
+        let mut source_list = SourcesList {
+            crlf: input.contains("\r\n"),
+            bom: input.starts_with(BOM),
+            original: Some(input.to_owned()),
+            ..Self::default()
+        };
+        for (no, line) in input.trim_start_matches(BOM).lines().enumerate() {
+            let entry = line.parse::<SourceLine>().unwrap_or_else(|why| {
+                errors.push(SourcesListError::BadLine { line: no, text: line.into(), why });
+                SourceLine::Invalid(line.into())
+            });
+
+            if !source_list.lines.contains(&entry) {
+                source_list.lines.push(entry);
+            }
+        }
+
+        (source_list, errors)
+    }
+
+    /// Parses a list without failing, keeping any lines that could not be parsed as
+    /// `SourceLine::Invalid`, so that they are written back unchanged.
+    pub(crate) fn parse_lenient(input: &str) -> Self {
+        Self::parse_collecting(input).0
+    }
+
+    /// The indexes of lines which could not be parsed.
+    pub fn invalid_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, line)| match line {
+                SourceLine::Invalid(_) => Some(pos),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fails with the error of the first invalid line, for callers which cannot accept them.
+    ///
+    /// The line number of the error is the index of the line within `lines`.
+    pub fn ensure_valid(&self) -> Result<(), SourcesListError> {
+        for (no, line) in self.lines.iter().enumerate() {
+            if let SourceLine::Invalid(ref text) = *line {
+                if let Err(why) = text.parse::<SourceLine>() {
+                    return Err(SourcesListError::BadLine { line: no, text: text.clone(), why });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SourcesList {
+    type Error = SourcesListError;
+    fn try_from(input: &'a [u8]) -> Result<Self, Self::Error> {
+        str::from_utf8(input)
+            .map_err(|why| SourcesListError::InvalidUtf8 { offset: why.valid_up_to(), why })?
+            .parse::<SourcesList>()
+    }
 }
 
 impl SourcesList {
     pub fn new<P: AsRef<Path>>(path: P) -> SourceResult<Self> {
+        Self::new_with(path, &ParseOptions::default())
+    }
+
+    /// Reads and parses the list at `path` with the given `options`.
+    pub fn new_with<P: AsRef<Path>>(path: P, options: &ParseOptions) -> SourceResult<Self> {
         let path = path.as_ref();
         let data = fs::read_to_string(path)
             .map_err(|why| SourceError::SourcesListOpen { path: path.to_path_buf(), why })?;
-        let mut sources_file = data.parse::<SourcesList>().map_err(|why| {
-            SourceError::SourcesList { path: path.to_path_buf(), why: Box::new(why) }
+        let parsed = if is_deb822_path(path) {
+            Self::parse_deb822(&data)
+        } else {
+            Self::parse_with(&data, options)
+        };
+
+        let mut sources_file = parsed.map_err(|why| SourceError::SourcesList {
+            path: path.to_path_buf(),
+            why: Box::new(why),
         })?;
 
         sources_file.path = path.to_path_buf();
         Ok(sources_file)
     }
 
+    /// A list of the `lines` at `path`, to be written in the format of its extension.
+    ///
+    /// The list is treated as not existing yet, so it is written in full by `write_sync`.
+    pub fn from_lines<P: AsRef<Path>>(path: P, lines: Vec<SourceLine>) -> Self {
+        let path = path.as_ref();
+        SourcesList {
+            path: path.to_path_buf(),
+            lines,
+            deb822: is_deb822_path(path),
+            ..Default::default()
+        }
+    }
+
+    /// The line ending which will be used when writing this list.
+    pub fn line_ending(&self) -> &'static str {
+        if self.crlf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
     pub fn contains_entry(&self, entry: &str) -> Option<usize> {
         self.lines.iter().position(|line| {
             if let SourceLine::Entry(e) = line {
@@ -69,160 +225,1518 @@ impl SourcesList {
             })
     }
 
-    pub fn is_active(&self) -> bool {
-        self.lines.iter().any(|line| if let SourceLine::Entry(_) = line { true } else { false })
-    }
+    /// Whether any entry in this list is enabled.
+    pub fn is_active(&self) -> bool {
+        self.lines.iter().any(|line| match line {
+            SourceLine::Entry(entry) => entry.enabled,
+            _ => false,
+        })
+    }
+
+    /// Writes the list to its path, through a temporary file beside it which then replaces the
+    /// list, so that the list on disk is either fully written or left untouched.
+    ///
+    /// Lists which were not parsed from a file are created, along with any missing parent
+    /// directories, such as when a new list was inserted into a fresh chroot.
+    ///
+    /// On Unix, the permissions of the list on disk are kept, and new lists are created as
+    /// `0644`, so that apt can read them when it drops its privileges.
+    pub fn write_sync(&mut self) -> io::Result<()> {
+        if self.original.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let temp = suffixed_path(&self.path, ".tmp");
+        let result = File::create(&temp)
+            .and_then(|mut file| {
+                write!(file, "{}", self)?;
+                #[cfg(unix)]
+                keep_permissions(&self.path, &file)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, &self.path));
+
+        if let Err(why) = result {
+            let _ = fs::remove_file(&temp);
+            return Err(why);
+        }
+
+        self.original = Some(self.to_string());
+        Ok(())
+    }
+
+    /// Re-renders every line in its canonical form, dropping any option which is overridden by
+    /// a later option of the same key.
+    ///
+    /// Returns `true` if the canonical form differs textually from the text that the list was
+    /// parsed from, meaning that the file should be rewritten.
+    pub fn normalize(&mut self) -> bool {
+        for line in &mut self.lines {
+            if let SourceLine::Entry(ref mut entry) = *line {
+                entry.dedup_options();
+            }
+        }
+
+        let rendered = Canonical(self).to_string();
+        let changed = match self.original {
+            Some(ref original) => original.lines().ne(rendered.lines()),
+            None => false,
+        };
+
+        self.original = Some(rendered);
+        changed
+    }
+
+    /// Collapses consecutive identical comments into a single comment.
+    ///
+    /// Returns `true` if any comment was removed.
+    pub fn dedup_comments(&mut self) -> bool {
+        let len = self.lines.len();
+        self.lines.dedup_by(|b, a| match (a, b) {
+            (SourceLine::Comment(a), SourceLine::Comment(b)) => a == b,
+            _ => false,
+        });
+
+        self.lines.len() != len
+    }
+
+    /// Splits every entry with several components into one line per component.
+    ///
+    /// Returns `true` if any entry was split.
+    pub fn explode(&mut self) -> bool {
+        let len = self.lines.len();
+        let mut lines = Vec::with_capacity(len);
+        for line in self.lines.drain(..) {
+            match line {
+                SourceLine::Entry(entry) => {
+                    lines.extend(entry.split_components().into_iter().map(SourceLine::Entry))
+                }
+                line => lines.push(line),
+            }
+        }
+
+        self.lines = lines;
+        self.lines.len() != len
+    }
+
+    /// Merges consecutive entries which differ only in their components into one entry, whose
+    /// components are those of each entry in the order that they were first given.
+    ///
+    /// Entries are only merged when their kind, URL, suite, options, and whether they are
+    /// enabled all match, and the later entry has no trailing comment to lose. Entries which are
+    /// separated by any other line, such as a comment, are left apart.
+    ///
+    /// Returns `true` if any entry was merged.
+    pub fn consolidate(&mut self) -> bool {
+        fn mergeable(previous: &SourceEntry, entry: &SourceEntry) -> bool {
+            entry.comment.is_none()
+                && (previous.enabled, previous.locked, previous.source)
+                    == (entry.enabled, entry.locked, entry.source)
+                && (&previous.url, &previous.suite, &previous.options)
+                    == (&entry.url, &entry.suite, &entry.options)
+        }
+
+        let len = self.lines.len();
+        let mut lines: Vec<SourceLine> = Vec::with_capacity(len);
+        for line in self.lines.drain(..) {
+            if let SourceLine::Entry(ref entry) = line {
+                if let Some(SourceLine::Entry(previous)) = lines.last_mut() {
+                    if mergeable(previous, entry) {
+                        for component in &entry.components {
+                            previous.add_component(component);
+                        }
+
+                        continue;
+                    }
+                }
+            }
+
+            lines.push(line);
+        }
+
+        self.lines = lines;
+        self.lines.len() != len
+    }
+
+    /// Streams each line of the list at `path` through `func`, without reading the whole list
+    /// into memory.
+    ///
+    /// Returning `None` from `func` deletes the line. The output is written to a temporary file
+    /// beside the original, which then replaces the original once every line was written.
+    ///
+    /// On Unix, the list keeps its permissions, as with `write_sync`.
+    ///
+    /// Deb822 `.sources` lists, whose entries span several lines, are rejected with
+    /// `io::ErrorKind::InvalidInput`.
+    pub fn transform_file<P, F>(path: P, mut func: F) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(SourceLine) -> Option<SourceLine>,
+    {
+        fn transform<F>(path: &Path, temp: &Path, func: &mut F) -> io::Result<()>
+        where
+            F: FnMut(SourceLine) -> Option<SourceLine>,
+        {
+            let mut reader = BufReader::new(File::open(path)?);
+            let file = File::create(temp)?;
+            #[cfg(unix)]
+            keep_permissions(path, &file)?;
+
+            let mut writer = BufWriter::new(file);
+            let mut buffer = String::new();
+            let mut no = 0;
+
+            while reader.read_line(&mut buffer)? != 0 {
+                let ending = if buffer.ends_with("\r\n") { "\r\n" } else { "\n" };
+                if no == 0 && buffer.starts_with(BOM) {
+                    write!(writer, "{}", BOM)?;
+                }
+
+                let line = buffer.trim_start_matches(BOM).parse::<SourceLine>().map_err(|why| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("parsing error on line {}: {}", no, why),
+                    )
+                })?;
+
+                if let Some(line) = func(line) {
+                    write!(writer, "{}{}", line, ending)?;
+                }
+
+                buffer.clear();
+                no += 1;
+            }
+
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        }
+
+        let path = path.as_ref();
+        if is_deb822_path(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot stream the deb822 list at '{}' by line", path.display()),
+            ));
+        }
+
+        let temp = suffixed_path(path, ".tmp");
+
+        match transform(path, &temp, &mut func) {
+            Ok(()) => fs::rename(&temp, path),
+            Err(why) => {
+                let _ = fs::remove_file(&temp);
+                Err(why)
+            }
+        }
+    }
+
+    pub fn reload(&mut self) -> SourceResult<()> {
+        *self = Self::new(&self.path)?;
+        Ok(())
+    }
+}
+
+impl SourcesList {
+    /// Renders the list, keeping the original text of any line which only differs from its
+    /// canonical form in whitespace, such as tabs used as field separators.
+    ///
+    /// This prevents the rewriting of a list from churning lines which were never changed.
+    fn render(&self, fmt: &mut Formatter, preserve_whitespace: bool) -> fmt::Result {
+        fn tokens(line: &str) -> String {
+            line.split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+
+        let mut raw_lines = HashMap::new();
+        if preserve_whitespace {
+            if let Some(ref original) = self.original {
+                for raw in original.trim_start_matches(BOM).lines() {
+                    raw_lines.entry(tokens(raw)).or_insert(raw);
+                }
+            }
+        }
+
+        if self.bom {
+            write!(fmt, "{}", BOM)?;
+        }
+
+        if self.deb822 {
+            return self.render_deb822(fmt, preserve_whitespace);
+        }
+
+        for line in &self.lines {
+            let rendered = match (line, self.lock_marker.as_ref()) {
+                (SourceLine::Entry(entry), Some(marker)) if !entry.enabled && entry.locked => {
+                    let rendered = line.to_string();
+                    [marker.as_str(), &rendered[LOCK_MARKER.len()..]].concat()
+                }
+                _ => line.to_string(),
+            };
+
+            match raw_lines.get(&tokens(&rendered)) {
+                Some(raw) => write!(fmt, "{}{}", raw, self.line_ending())?,
+                None => write!(fmt, "{}{}", rendered, self.line_ending())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for SourcesList {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        self.render(fmt, true)
+    }
+}
+
+/// Renders a list in its canonical form, without preserving the whitespace of the original.
+struct Canonical<'a>(&'a SourcesList);
+
+impl<'a> Display for Canonical<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        self.0.render(fmt, false)
+    }
+}
+
+impl Index<usize> for SourcesList {
+    type Output = SourceLine;
+    fn index(&self, index: usize) -> &SourceLine {
+        &self.lines[index]
+    }
+}
+
+/// The pockets which every release is expected to have enabled.
+pub const STANDARD_POCKETS: &[&str] = &["security", "updates"];
+
+/// Suites of the official archives which name a release by its role rather than its codename,
+/// and do not follow the pockets of a codename.
+const RELEASE_ALIASES: &[&str] =
+    &["stable", "testing", "unstable", "oldstable", "oldoldstable", "experimental", "sid", "devel"];
+
+/// How `SourcesLists::remove_entry` removes an entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RemovePolicy {
+    /// Delete the line from the list.
+    Delete,
+    /// Comment out the line, so that it may be restored later.
+    Disable,
+}
+
+#[derive(Clone, Debug)]
+/// Stores all apt source information fetched from the system.
+pub struct SourcesLists {
+    pub(crate) files: Vec<SourcesList>,
+    pub(crate) modified: Vec<u16>,
+    /// Notes attached to entries, which are never written to the lists.
+    pub(crate) annotations: HashMap<EntryKey, String>,
+}
+
+impl Deref for SourcesLists {
+    type Target = Vec<SourcesList>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.files
+    }
+}
+
+impl DerefMut for SourcesLists {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.files
+    }
+}
+
+impl SourcesLists {
+    /// Scans every `.list` and `.sources` file in **/etc/apt/sources.list.d**, including
+    /// **/etc/apt/sources.list**.
+    ///
+    /// Note that this will parse every source list into memory before returning.
+    pub fn scan() -> SourceResult<Self> {
+        Self::scan_from_root("/")
+    }
+
+    /// Scans the lists at the standard locations, parsing them with the given `options`, such
+    /// as the lock marker of `ParseOptions::locks`.
+    pub fn scan_with(options: &ParseOptions) -> SourceResult<Self> {
+        Self::scan_from_root_with("/", options)
+    }
+
+    /// Scans the lists at the standard locations within a `root`, such as the root filesystem
+    /// of a chroot or a mounted image.
+    pub fn scan_from_root<P: AsRef<Path>>(root: P) -> SourceResult<Self> {
+        Self::scan_from_apt_config(&AptConfig::with_root(root))
+    }
+
+    /// Scans the lists at the standard locations within a `root`, parsing them with the given
+    /// `options`.
+    pub fn scan_from_root_with<P: AsRef<Path>>(
+        root: P,
+        options: &ParseOptions,
+    ) -> SourceResult<Self> {
+        Self::new_from_paths_with(list_paths(&AptConfig::with_root(root))?.iter(), options)
+    }
+
+    /// Scans the lists at the locations given by apt's configuration, rather than the standard
+    /// locations.
+    pub fn scan_from_apt_config(conf: &AptConfig) -> SourceResult<Self> {
+        Self::new_from_paths(list_paths(conf)?.iter())
+    }
+
+    /// Scans the source lists within a `root`, only parsing those accepted by the `predicate`.
+    ///
+    /// This allows a tool to focus on the lists that it manages, such as `myapp-*.list`,
+    /// without loading every other list on the system.
+    pub fn scan_filtered<P: AsRef<Path>, F: Fn(&Path) -> bool>(
+        root: P,
+        predicate: F,
+    ) -> SourceResult<Self> {
+        let paths = list_paths(&AptConfig::with_root(root))?;
+        Self::new_from_paths(paths.iter().filter(|path| predicate(path)))
+    }
+
+    /// Scans the source lists within a `root`, only parsing those modified after `since`.
+    ///
+    /// The paths of the lists which were skipped as unchanged are returned alongside, so that a
+    /// tool which re-reads its configuration on change can keep its previous copies of them.
+    pub fn scan_since<P: AsRef<Path>>(
+        root: P,
+        since: SystemTime,
+    ) -> SourceResult<(Self, Vec<PathBuf>)> {
+        let mut newer = Vec::new();
+        let mut skipped = Vec::new();
+        for path in list_paths(&AptConfig::with_root(root))? {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|why| SourceError::SourcesListOpen { path: path.clone(), why })?;
+
+            if modified > since {
+                newer.push(path);
+            } else {
+                skipped.push(path);
+            }
+        }
+
+        Ok((Self::new_from_paths(newer.iter())?, skipped))
+    }
+
+    /// Finds and parses the backup lists that apt and this crate leave behind within a `root`.
+    ///
+    /// Backups of `.list` and `.sources` files, ending with `.save`, `.distUpgrade`, or
+    /// `.dpkg-old`, are collected from **$root/etc/apt** and **$root/etc/apt/sources.list.d**.
+    /// As backups may be stale, lines which fail to parse are kept as `SourceLine::Invalid`,
+    /// and files which are not valid UTF-8 are skipped with a warning, rather than causing an
+    /// error.
+    pub fn scan_backups(root: &Path) -> SourceResult<Vec<(PathBuf, SourcesList)>> {
+        const BACKUP_EXTENSIONS: &[&str] = &["save", "distUpgrade", "dpkg-old"];
+
+        let mut backups = Vec::new();
+        for dir in &[root.join("etc/apt"), root.join("etc/apt/sources.list.d")] {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(ref why) if why.kind() == io::ErrorKind::NotFound => continue,
+                Err(why) => return Err(why.into()),
+            };
+
+            for entry in entries {
+                let path = entry?.path();
+                let is_backup = match path.extension().and_then(|e| e.to_str()) {
+                    Some(extension) => BACKUP_EXTENSIONS.contains(&extension),
+                    None => false,
+                };
+
+                let stem = match path.file_stem() {
+                    Some(stem) => Path::new(stem),
+                    None => continue,
+                };
+
+                let is_list = match stem.extension().and_then(|e| e.to_str()) {
+                    Some(extension) => extension == "list" || extension == "sources",
+                    None => false,
+                };
+
+                if !is_backup || !is_list || !path.is_file() {
+                    continue;
+                }
+
+                let data = match fs::read_to_string(&path) {
+                    Ok(data) => data,
+                    Err(ref why) if why.kind() == io::ErrorKind::InvalidData => {
+                        eprintln!("skipping backup at {:?}: {}", path, why);
+                        continue;
+                    }
+                    Err(why) => return Err(SourceError::SourcesListOpen { path, why }),
+                };
+
+                let mut list = if is_deb822_path(stem) {
+                    SourcesList::parse_deb822(&data)
+                        .unwrap_or_else(|_| SourcesList::parse_lenient(&data))
+                } else {
+                    SourcesList::parse_lenient(&data)
+                };
+                list.path = path.clone();
+                backups.push((path, list));
+            }
+        }
+
+        backups.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(backups)
+    }
+
+    /// When given a list of paths to source lists, this will attempt to parse them.
+    pub fn new_from_paths<P: AsRef<Path>, I: Iterator<Item = P>>(paths: I) -> SourceResult<Self> {
+        Self::new_from_paths_with(paths, &ParseOptions::default())
+    }
+
+    /// Parses the lists at the given paths with the given `options`.
+    pub fn new_from_paths_with<P: AsRef<Path>, I: Iterator<Item = P>>(
+        paths: I,
+        options: &ParseOptions,
+    ) -> SourceResult<Self> {
+        let files = paths
+            .map(|path| SourcesList::new_with(path, options))
+            .collect::<SourceResult<Vec<SourcesList>>>()?;
+
+        Ok(Self::from_lists(files))
+    }
+
+    /// Parses the lists at the given paths, collecting the error of each list which could not
+    /// be opened or parsed rather than failing on the first.
+    pub fn new_from_paths_lenient<P: AsRef<Path>, I: Iterator<Item = P>>(
+        paths: I,
+    ) -> (Self, Vec<(PathBuf, SourceError)>) {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for path in paths {
+            match SourcesList::new(&path) {
+                Ok(list) => files.push(list),
+                Err(why) => errors.push((path.as_ref().to_path_buf(), why)),
+            }
+        }
+
+        (Self::from_lists(files), errors)
+    }
+
+    /// Constructs a set from lists which have already been parsed, with none marked as modified.
+    pub fn from_lists(files: Vec<SourcesList>) -> Self {
+        SourcesLists {
+            modified: Vec::with_capacity(files.len()),
+            files,
+            annotations: HashMap::new(),
+        }
+    }
+
+    /// Follows the `# include: <path>` directives within each list, appending every referenced
+    /// list to the set.
+    ///
+    /// Relative paths are resolved against `root`. Directive comments are recognized here even
+    /// if the lists were parsed without `ParseOptions::includes`, and included lists are parsed
+    /// with includes enabled so that nested directives are followed as well. Each list is only
+    /// loaded once.
+    ///
+    /// Returns the provenance of each include, as `(included, includer)` pairs. An error is
+    /// returned if the lists include each other in a cycle, or an included list cannot be
+    /// loaded, in which case the set is left unchanged.
+    pub fn resolve_includes<P: AsRef<Path>>(
+        &mut self,
+        root: P,
+    ) -> SourceResult<Vec<(PathBuf, PathBuf)>> {
+        let root = root.as_ref();
+        let options = ParseOptions { includes: true, ..ParseOptions::default() };
+        let mut provenance = Vec::new();
+
+        // Included lists are collected aside, so that the set is left as it was on failure.
+        let mut included: Vec<SourcesList> = Vec::new();
+        let mut id = 0;
+        while id < self.files.len() + included.len() {
+            let list = match self.files.get(id) {
+                Some(list) => list,
+                None => &included[id - self.files.len()],
+            };
+
+            let includes: Vec<PathBuf> = list
+                .lines
+                .iter()
+                .filter_map(|line| match *line {
+                    SourceLine::Comment(ref comment) => include_directive(comment).map(Path::new),
+                    SourceLine::Include(ref path) => Some(path.as_path()),
+                    _ => None,
+                })
+                .map(|path| root.join(path))
+                .collect();
+
+            let includer = list.path.clone();
+            for path in includes {
+                provenance.push((path.clone(), includer.clone()));
+                if !self.files.iter().chain(&included).any(|list| list.path == path) {
+                    included.push(SourcesList::new_with(&path, &options)?);
+                }
+            }
+
+            id += 1;
+        }
+
+        if let Some(path) = include_cycle(&provenance) {
+            return Err(SourceError::IncludeCycle { path });
+        }
+
+        for line in self.files.iter_mut().flat_map(|list| list.lines.iter_mut()) {
+            let path = match *line {
+                SourceLine::Comment(ref comment) => include_directive(comment).map(PathBuf::from),
+                _ => None,
+            };
+
+            if let Some(path) = path {
+                *line = SourceLine::Include(path);
+            }
+        }
+
+        self.files.extend(included);
+        Ok(provenance)
+    }
+
+    /// Specify to enable or disable a repo. `true` is returned if the repo was found.
+    ///
+    /// Locked entries are not enabled.
+    pub fn repo_modify(&mut self, repo: &str, enabled: bool) -> bool {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let iterator = files
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(pos, list)| list.get_entries_mut(repo).map(move |e| (pos, e)));
+
+        let mut found = false;
+        for (pos, entry) in iterator {
+            found = true;
+            if enabled && entry.locked {
+                continue;
+            }
+
+            add_modified(modified, pos as u16);
+            entry.enabled = enabled;
+        }
+
+        found
+    }
+
+    /// Whether the repo is enabled on the given suite, or `None` if it has no entries.
+    ///
+    /// The repo is considered enabled if any of its matching entries are enabled.
+    pub fn is_repo_enabled(&self, repo: &str, suite: &str) -> Option<bool> {
+        let mut entries =
+            self.entries().filter(|entry| entry.url == repo && entry.suite == suite).peekable();
+
+        entries.peek()?;
+        Some(entries.any(|entry| entry.enabled))
+    }
+
+    /// Appends a component to each entry of the repo on the release of the `suite`.
+    ///
+    /// Every pocket of the release is affected, so adding `multiverse` to `disco` will also
+    /// add it to `disco-updates` and `disco-security`. `true` is returned if any entry changed.
+    pub fn add_component_to(&mut self, repo: &str, suite: &str, component: &str) -> bool {
+        let release = split_suite(suite).0;
+        let mut found = false;
+        self.entries_mut(|entry| {
+            let changed = entry.url == repo
+                && entry.matches_release(release)
+                && entry.add_component(component);
+            found |= changed;
+            changed
+        });
+
+        found
+    }
+
+    /// Appends a component only to the entries of the repo on exactly the `suite`.
+    ///
+    /// Unlike `add_component_to`, other pockets of the release are left untouched, so adding
+    /// `universe` to `disco` will not add it to `disco-proposed`. `true` is returned if any
+    /// entry changed.
+    pub fn add_component_for_suite(&mut self, repo: &str, suite: &str, component: &str) -> bool {
+        let mut found = false;
+        self.entries_mut(|entry| {
+            let changed =
+                entry.url == repo && entry.suite == suite && entry.add_component(component);
+            found |= changed;
+            changed
+        });
+
+        found
+    }
+
+    /// Switches every entry to `https` whose host is known to support it.
+    ///
+    /// Returns the number of entries which were changed.
+    pub fn upgrade_http_to_https(&mut self) -> usize {
+        let mut count = 0;
+        self.entries_mut(|entry| match entry.https_upgrade_candidate() {
+            Some(upgraded) => {
+                *entry = upgraded;
+                count += 1;
+                true
+            }
+            None => false,
+        });
+
+        count
+    }
+
+    /// Constructs an iterator of all source entries from a sources list.
+    ///
+    /// Disabled entries, which are commented out in the list, are included. Use
+    /// `SourcesLists::active_entries` to only fetch the entries that apt will use.
+    pub fn entries(&self) -> impl Iterator<Item = &SourceEntry> {
+        self.iter().flat_map(|list| list.lines.iter()).filter_map(move |entry| {
+            if let SourceLine::Entry(entry) = entry {
+                return Some(entry);
+            }
+
+            None
+        })
+    }
+
+    /// Consumes the lists, returning each of the source entries that `entries` would yield.
+    pub fn into_entries(self) -> Vec<SourceEntry> {
+        self.files
+            .into_iter()
+            .flat_map(|list| list.lines)
+            .filter_map(|line| match line {
+                SourceLine::Entry(entry) => Some(entry),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Clones each of the source entries that `entries` would yield.
+    pub fn cloned_entries(&self) -> Vec<SourceEntry> {
+        self.entries().cloned().collect()
+    }
+
+    /// Constructs an iterator of enabled source entries from a sources list.
+    pub fn active_entries(&self) -> impl Iterator<Item = &SourceEntry> {
+        self.entries().filter(|entry| entry.enabled)
+    }
+
+    /// Maps a flat index across the entries of every list to the index of its list, and the
+    /// index of its line within that list.
+    ///
+    /// Only `SourceLine::Entry` lines are counted, in the same order as `entries()`.
+    pub fn entry_at(&self, index: usize) -> Option<(usize, usize)> {
+        self.files
+            .iter()
+            .enumerate()
+            .flat_map(|(id, list)| {
+                list.lines.iter().enumerate().filter_map(move |(pos, line)| match line {
+                    SourceLine::Entry(_) => Some((id, pos)),
+                    _ => None,
+                })
+            })
+            .nth(index)
+    }
+
+    /// Finds the first entry for the `url` and `suite`, along with the path of its list and the
+    /// index of its line within that list.
+    pub fn find_entry(&self, url: &str, suite: &str) -> Option<(&Path, usize, &SourceEntry)> {
+        self.files.iter().find_map(|list| {
+            list.lines.iter().enumerate().find_map(|(pos, line)| match line {
+                SourceLine::Entry(entry) if entry.url == url && entry.suite == suite => {
+                    Some((list.path.as_path(), pos, entry))
+                }
+                _ => None,
+            })
+        })
+    }
+
+    /// Whether both sets of lists have the same active entries, regardless of which list each
+    /// entry is in, the order of the entries, or any comments and disabled entries.
+    ///
+    /// Entries are compared in a normalized form, where the order of their components and
+    /// options, options overridden by a later option, and trailing slashes of URLs are
+    /// insignificant.
+    pub fn semantically_eq(&self, other: &SourcesLists) -> bool {
+        fn normalized(lists: &SourcesLists) -> BTreeSet<SemanticKey> {
+            lists.active_entries().map(semantic_key).collect()
+        }
+
+        normalized(self) == normalized(other)
+    }
+
+    /// Finds active entries which are declared more than once, which apt warns about.
+    ///
+    /// Entries are duplicates if they share their kind, URL, suite, and architectures,
+    /// regardless of their components. Each duplicate is returned as its first declaration,
+    /// with the path of the list of every declaration, in the order that they were declared.
+    pub fn find_duplicates(&self) -> Vec<(SourceEntry, Vec<PathBuf>)> {
+        let mut declarations: Vec<(_, SourceEntry, Vec<PathBuf>)> = Vec::new();
+        for list in self.iter() {
+            for line in &list.lines {
+                let entry = match line {
+                    SourceLine::Entry(entry) if entry.enabled => entry,
+                    _ => continue,
+                };
+
+                let mut architectures = entry.architectures();
+                architectures.sort();
+                let key = (entry.source, entry.url(), entry.suite.as_str(), architectures);
+
+                match declarations.iter_mut().find(|(k, ..)| *k == key) {
+                    Some((_, _, paths)) => paths.push(list.path.clone()),
+                    None => declarations.push((key, entry.clone(), vec![list.path.clone()])),
+                }
+            }
+        }
+
+        declarations
+            .into_iter()
+            .filter(|(_, _, paths)| paths.len() > 1)
+            .map(|(_, entry, paths)| (entry, paths))
+            .collect()
+    }
+
+    /// Collects the keyring path of every `signed-by` option across the active entries.
+    ///
+    /// Only absolute paths are collected, so fingerprints and inline armored keys are excluded.
+    pub fn referenced_keyrings(&self) -> BTreeSet<PathBuf> {
+        self.active_entries()
+            .flat_map(SourceEntry::signed_by_all)
+            .filter(|path| path.is_absolute())
+            .collect()
+    }
+
+    /// Active entries which have neither a `signed-by` option nor `trusted=yes`, along with the
+    /// path of their list.
+    ///
+    /// Newer releases of apt refuse to use repositories without a `signed-by` keyring, so these
+    /// entries should be fixed before they cause `apt update` to fail.
+    pub fn unsigned_entries(&self) -> impl Iterator<Item = (&Path, &SourceEntry)> {
+        self.files
+            .iter()
+            .flat_map(|list| {
+                list.lines.iter().filter_map(move |line| match line {
+                    SourceLine::Entry(entry) if entry.enabled => Some((list.path.as_path(), entry)),
+                    _ => None,
+                })
+            })
+            .filter(|(_, entry)| {
+                let trusted = match entry.option_bool("trusted") {
+                    Some(Ok(trusted)) => trusted,
+                    _ => false,
+                };
+
+                !trusted && entry.option("signed-by").is_none()
+            })
+    }
+
+    /// The `add-apt-repository` commands which would recreate the active entries.
+    ///
+    /// Launchpad PPAs are added by their `ppa:` shortcut, with `-s` when their sources are
+    /// enabled too. Every other entry is added by its full line.
+    pub fn to_commands(&self) -> Vec<String> {
+        let with_sources: HashSet<String> = self
+            .active_entries()
+            .filter(|entry| entry.source)
+            .filter_map(SourceEntry::ppa_shortcut)
+            .collect();
+
+        let mut commands = Vec::new();
+        for entry in self.active_entries() {
+            let command = match entry.ppa_shortcut() {
+                Some(ref ppa) if with_sources.contains(ppa) => {
+                    format!("add-apt-repository -s {}", ppa)
+                }
+                Some(ppa) => format!("add-apt-repository {}", ppa),
+                None => format!("add-apt-repository '{}'", entry),
+            };
+
+            if !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+
+        commands
+    }
+
+    /// Every index URL that apt would fetch for the active entries, without duplicates.
+    ///
+    /// See `SourceEntry::index_urls` for the files which are included.
+    pub fn metadata_urls(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.active_entries()
+            .flat_map(SourceEntry::index_urls)
+            .filter(|url| seen.insert(url.clone()))
+            .collect()
+    }
+
+    /// The active entries, ordered by the pocket of their suite as apt would fetch them.
+    ///
+    /// The `security` pocket comes first, followed by `updates`, the release itself, and then
+    /// `backports` and `proposed`. Within each pocket, entries are grouped by host in the order
+    /// that each host first appears.
+    pub fn entries_fetch_order(&self) -> Vec<&SourceEntry> {
+        fn pocket_rank(entry: &SourceEntry) -> u8 {
+            match entry.pocket() {
+                Some("security") => 0,
+                Some("updates") => 1,
+                None => 2,
+                Some("backports") | Some("proposed") => 3,
+                Some(_) => 4,
+            }
+        }
+
+        let mut hosts: Vec<Option<&str>> = Vec::new();
+        for entry in self.active_entries() {
+            if !hosts.contains(&entry.host()) {
+                hosts.push(entry.host());
+            }
+        }
+
+        let mut entries: Vec<&SourceEntry> = self.active_entries().collect();
+        entries.sort_by_key(|entry| {
+            (pocket_rank(entry), hosts.iter().position(|&host| host == entry.host()))
+        });
+        entries
+    }
+
+    /// The base release of the active Ubuntu and Debian archive entries, if they all agree.
+    ///
+    /// PPAs and other third party repositories are ignored, as they often lag behind the
+    /// system's release. Entries of mixed releases will block a release upgrade.
+    pub fn current_release(&self) -> Result<String, ReleaseError> {
+        let releases: BTreeSet<&str> = self
+            .active_entries()
+            .filter(|entry| {
+                [ArchiveKind::Ubuntu, ArchiveKind::Debian].contains(&entry.archive_kind())
+            })
+            .map(SourceEntry::release)
+            .collect();
+
+        let mut releases: Vec<String> = releases.into_iter().map(String::from).collect();
+        match releases.len() {
+            0 => Err(ReleaseError::NoOfficialEntries),
+            1 => Ok(releases.remove(0)),
+            _ => Err(ReleaseError::Conflict { releases }),
+        }
+    }
+
+    /// For each release with an active entry, the pockets from `STANDARD_POCKETS` which have
+    /// no active entries.
+    ///
+    /// A release without a `security` pocket, for example, will not receive security updates.
+    /// Releases which have every pocket are omitted. Only the releases of the Ubuntu and Debian
+    /// archives are checked, by their codenames, as other repositories rarely have pockets.
+    pub fn missing_pockets(&self) -> Vec<(String, Vec<String>)> {
+        self.missing_pockets_of(STANDARD_POCKETS)
+    }
+
+    /// As `missing_pockets`, but checking for the given `pockets` instead.
+    pub fn missing_pockets_of(&self, pockets: &[&str]) -> Vec<(String, Vec<String>)> {
+        let codename = |entry: &SourceEntry| {
+            is_official_archive(entry)
+                && !entry.suite.ends_with('/')
+                && entry.suite.parse::<Suite>().is_ok()
+                && !RELEASE_ALIASES.contains(&entry.release())
+        };
+
+        let mut releases: Vec<&str> = Vec::new();
+        for entry in self.active_entries().filter(|entry| entry.pocket().is_none()) {
+            if codename(entry) && !releases.contains(&entry.suite.as_str()) {
+                releases.push(&entry.suite);
+            }
+        }
+
+        releases
+            .into_iter()
+            .filter_map(|release| {
+                let missing: Vec<String> = pockets
+                    .iter()
+                    .filter(|&&pocket| {
+                        !self.active_entries().any(|entry| {
+                            codename(entry)
+                                && entry.release() == release
+                                && entry.pocket() == Some(pocket)
+                        })
+                    })
+                    .map(|&pocket| pocket.to_owned())
+                    .collect();
+
+                if missing.is_empty() {
+                    None
+                } else {
+                    Some((release.to_owned(), missing))
+                }
+            })
+            .collect()
+    }
+
+    /// Identifies the mirror that is most commonly used by the official archive entries.
+    ///
+    /// This is a heuristic which considers active entries that have the `main` component,
+    /// ignoring PPAs and the security archive.
+    pub fn primary_mirror(&self) -> Option<&str> {
+        let mut hosts: Vec<(&str, usize)> = Vec::new();
+
+        let archives = self
+            .active_entries()
+            .filter(|entry| entry.components.iter().any(|c| c == "main"))
+            .filter_map(SourceEntry::host)
+            .filter(|&host| host != "ppa.launchpad.net" && host != "security.ubuntu.com");
+
+        for host in archives {
+            match hosts.iter_mut().find(|&&mut (h, _)| h == host) {
+                Some(&mut (_, ref mut count)) => *count += 1,
+                None => hosts.push((host, 1)),
+            }
+        }
+
+        let mut primary = None;
+        let mut max = 0;
+        for (host, count) in hosts {
+            if count > max {
+                primary = Some(host);
+                max = count;
+            }
+        }
+
+        primary
+    }
+
+    /// A callback-based iterator that tracks which files have been modified.
+    pub fn entries_mut<F: FnMut(&mut SourceEntry) -> bool>(&mut self, mut func: F) {
+        let &mut Self { ref mut files, ref mut modified, .. } = self;
+        for (pos, list) in files.iter_mut().enumerate() {
+            for entry in &mut list.lines {
+                if let SourceLine::Entry(entry) = entry {
+                    if func(entry) {
+                        add_modified(modified, pos as u16)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Insert a source entry to the lists.
+    ///
+    /// If the entry already exists, it will be modified.
+    /// Otherwise, the entry will be added to the preferred list.
+    /// If the preferred list does not exist, it will be created.
+    pub fn insert_entry<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        entry: SourceEntry,
+    ) -> SourceResult<()> {
+        let path = path.as_ref();
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        for (id, list) in files.iter_mut().enumerate() {
+            if list.path == path {
+                match list.contains_entry(&entry.url) {
+                    Some(pos) => list.lines[pos] = SourceLine::Entry(entry),
+                    None => list.lines.push(SourceLine::Entry(entry)),
+                }
+
+                add_modified(modified, id as u16);
+                return Ok(());
+            }
+        }
+
+        files.push(SourcesList::from_lines(path, vec![SourceLine::Entry(entry)]));
+        add_modified(modified, files.len() as u16 - 1);
+
+        Ok(())
+    }
+
+    /// Inserts the entry into the list where `add-apt-repository` would have placed it, creating
+    /// that list if needed.
+    ///
+    /// See `SourceEntry::list_filename` for the conventions which are followed. Returns the path
+    /// of the list which the entry was inserted into.
+    pub fn place_entry(&mut self, entry: SourceEntry) -> SourceResult<PathBuf> {
+        self.place_entry_with(&AptConfig::standard(), entry)
+    }
+
+    /// Places the entry as `place_entry` does, within the locations given by apt's
+    /// configuration.
+    pub fn place_entry_with(
+        &mut self,
+        conf: &AptConfig,
+        entry: SourceEntry,
+    ) -> SourceResult<PathBuf> {
+        let path = match entry.list_filename() {
+            Some(filename) => conf.sourceparts.join(filename),
+            None => conf.sourcelist.clone(),
+        };
+
+        self.insert_entry(&path, entry)?;
+        Ok(path)
+    }
+
+    /// Insert a source entry into a list which is kept sorted, at the position which keeps its
+    /// entries in order.
+    ///
+    /// Comments and empty lines at the top of the list are kept in place, with the entry placed
+    /// before the first later entry. Comments directly above that entry belong to it, so the
+    /// entry is placed above them. Nothing is inserted if the list already has the entry.
+    pub fn insert_entry_sorted<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        entry: SourceEntry,
+    ) -> SourceResult<()> {
+        let path = path.as_ref();
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let (id, list) = match files.iter_mut().enumerate().find(|(_, list)| list.path == path) {
+            Some(found) => found,
+            None => {
+                files.push(SourcesList::from_lines(path, vec![SourceLine::Entry(entry)]));
+                add_modified(modified, files.len() as u16 - 1);
+                return Ok(());
+            }
+        };
+
+        let mut header = 0;
+        while header < list.lines.len() {
+            match list.lines[header] {
+                SourceLine::Comment(_) | SourceLine::Empty => header += 1,
+                _ => break,
+            }
+        }
+
+        let mut pos = list.lines.len();
+        for (index, line) in list.lines.iter().enumerate().skip(header) {
+            if let SourceLine::Entry(ref existing) = *line {
+                if *existing == entry {
+                    return Ok(());
+                } else if *existing > entry {
+                    pos = index;
+                    break;
+                }
+            }
+        }
+
+        while pos > header {
+            match list.lines[pos - 1] {
+                SourceLine::Comment(_) => pos -= 1,
+                _ => break,
+            }
+        }
+
+        list.lines.insert(pos, SourceLine::Entry(entry));
+        add_modified(modified, id as u16);
+        Ok(())
+    }
+
+    /// Insert a source entry to the lists, merging it into an existing entry of the same type,
+    /// URL, and suite within that list.
+    ///
+    /// Unlike `insert_entry`, the components of the existing entry are kept, with any new
+    /// components appended. Options of the new entry replace those with the same key, and other
+    /// options are kept. The existing entry takes on whether the new entry is enabled.
+    /// If no such entry exists, the entry is inserted as by `insert_entry`.
+    pub fn insert_entry_merge<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        entry: SourceEntry,
+    ) -> SourceResult<()> {
+        let path = path.as_ref();
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let key = entry_key(&entry);
+        let existing = files.iter_mut().enumerate().filter(|(_, list)| list.path == path).find_map(
+            |(id, list)| {
+                list.lines.iter_mut().find_map(|line| match line {
+                    SourceLine::Entry(existing) if entry_key(existing) == key => {
+                        Some((id, existing))
+                    }
+                    _ => None,
+                })
+            },
+        );
+
+        match existing {
+            Some((id, existing)) => {
+                for component in &entry.components {
+                    existing.add_component(component);
+                }
+
+                existing.options =
+                    merge_options(existing.options.as_deref(), entry.options.as_deref());
+                existing.enabled = entry.enabled;
+                add_modified(modified, id as u16);
+                Ok(())
+            }
+            None => self.insert_entry(path, entry),
+        }
+    }
+
+    /// Insert a source entry to the lists, with a descriptive comment placed above it.
+    ///
+    /// The comment is written after the `COMMENT_MARKER`. If an entry of the same type, URL,
+    /// and suite already exists, it will be modified. The comment directly above it is replaced
+    /// if it begins with the marker or already has the same text, and otherwise kept, with the
+    /// new comment inserted below it. If the entry does not exist, the comment and entry are
+    /// added to the preferred list.
+    pub fn insert_entry_with_comment<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        comment: &str,
+        entry: SourceEntry,
+    ) -> SourceResult<()> {
+        let path = path.as_ref();
+        let comment = comment.trim();
+        let text = [COMMENT_MARKER, " ", comment].concat();
+        let key = entry_key(&entry);
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        for (id, list) in files.iter_mut().enumerate() {
+            if list.path == path {
+                let existing = list.lines.iter().position(|line| match line {
+                    SourceLine::Entry(current) => entry_key(current) == key,
+                    _ => false,
+                });
+
+                match existing {
+                    Some(pos) => {
+                        list.lines[pos] = SourceLine::Entry(entry);
+                        let replace = match pos.checked_sub(1).map(|above| &list.lines[above]) {
+                            Some(SourceLine::Comment(above)) => {
+                                above.starts_with(COMMENT_MARKER)
+                                    || above.trim_start_matches('#').trim() == comment
+                            }
+                            _ => false,
+                        };
+
+                        if replace {
+                            list.lines[pos - 1] = SourceLine::Comment(text);
+                        } else {
+                            list.lines.insert(pos, SourceLine::Comment(text));
+                        }
+                    }
+                    None => {
+                        list.lines.push(SourceLine::Comment(text));
+                        list.lines.push(SourceLine::Entry(entry));
+                    }
+                }
+
+                add_modified(modified, id as u16);
+                return Ok(());
+            }
+        }
+
+        let lines = vec![SourceLine::Comment(text), SourceLine::Entry(entry)];
+        files.push(SourcesList::from_lines(path, lines));
 
-    pub fn write_sync(&mut self) -> io::Result<()> {
-        fs::OpenOptions::new()
-            .truncate(true)
-            .write(true)
-            .open(&self.path)
-            .and_then(|mut file| writeln!(&mut file, "{}", self))
-    }
+        add_modified(modified, files.len() as u16 - 1);
 
-    pub fn reload(&mut self) -> SourceResult<()> {
-        *self = Self::new(&self.path)?;
         Ok(())
     }
-}
 
-impl Display for SourcesList {
-    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        for line in &self.lines {
-            writeln!(fmt, "{}", line)?;
+    /// Clone each entry of `repo` on the `from_suite` into the `to_suite`.
+    ///
+    /// The clone is inserted directly after the original entry, unless an identical entry
+    /// already exists within that list. `true` is returned if any clone was inserted.
+    pub fn insert_suite_clone(&mut self, repo: &str, from_suite: &str, to_suite: &str) -> bool {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let mut found = false;
+        for (id, list) in files.iter_mut().enumerate() {
+            let mut pos = 0;
+            while pos < list.lines.len() {
+                let clone = match list.lines[pos] {
+                    SourceLine::Entry(ref entry)
+                        if entry.url == repo && entry.suite == from_suite =>
+                    {
+                        entry.with_suite(to_suite)
+                    }
+                    _ => {
+                        pos += 1;
+                        continue;
+                    }
+                };
+
+                pos += 1;
+                if !list.lines.contains(&SourceLine::Entry(clone.clone())) {
+                    list.lines.insert(pos, SourceLine::Entry(clone));
+                    add_modified(modified, id as u16);
+                    found = true;
+                    pos += 1;
+                }
+            }
         }
 
-        Ok(())
+        found
     }
-}
 
-#[derive(Clone, Debug)]
-/// Stores all apt source information fetched from the system.
-pub struct SourcesLists {
-    pub(crate) files: Vec<SourcesList>,
-    pub(crate) modified: Vec<u16>,
-}
+    /// Ensure that each entry of `repo` on the `suite` has a twin of the other kind, adding a
+    /// `deb-src` line for a `deb` line, or vice versa.
+    ///
+    /// The twin is inserted directly after the original entry, unless that list already has an
+    /// entry of the other kind for the same repo and suite. `true` is returned if any twin was
+    /// inserted.
+    pub fn ensure_twin(&mut self, repo: &str, suite: &str) -> bool {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
 
-impl Deref for SourcesLists {
-    type Target = Vec<SourcesList>;
+        let mut found = false;
+        for (id, list) in files.iter_mut().enumerate() {
+            let mut pos = 0;
+            while pos < list.lines.len() {
+                let twin = match list.lines[pos] {
+                    SourceLine::Entry(ref entry) if entry.url == repo && entry.suite == suite => {
+                        if entry.source {
+                            entry.as_binary()
+                        } else {
+                            entry.as_source()
+                        }
+                    }
+                    _ => {
+                        pos += 1;
+                        continue;
+                    }
+                };
 
-    fn deref(&self) -> &Self::Target {
-        &self.files
-    }
-}
+                pos += 1;
+                let exists = list.lines.iter().any(|line| match line {
+                    SourceLine::Entry(entry) => {
+                        entry.url == repo && entry.suite == suite && entry.source == twin.source
+                    }
+                    _ => false,
+                });
 
-impl DerefMut for SourcesLists {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.files
+                if !exists {
+                    list.lines.insert(pos, SourceLine::Entry(twin));
+                    add_modified(modified, id as u16);
+                    found = true;
+                    pos += 1;
+                }
+            }
+        }
+
+        found
     }
-}
 
-impl SourcesLists {
-    /// Scans every file in **/etc/apt/sources.list.d**, including **/etc/apt/sources.list**.
+    /// Ensure that every enabled `deb` entry on any pocket of the `release` has an enabled
+    /// `deb-src` twin in its list.
     ///
-    /// Note that this will parse every source list into memory before returning.
-    pub fn scan() -> SourceResult<Self> {
-        let mut paths = vec![PathBuf::from("/etc/apt/sources.list")];
+    /// Disabled twins are enabled unless they are locked, and missing twins are inserted directly
+    /// after their `deb` entry. Returns the number of `deb-src` entries which were enabled or
+    /// inserted.
+    pub fn enable_source_for_release(&mut self, release: &str) -> usize {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let mut count = 0;
+        for (id, list) in files.iter_mut().enumerate() {
+            let mut pos = 0;
+            while pos < list.lines.len() {
+                pos += 1;
+                let twin = match list.lines[pos - 1] {
+                    SourceLine::Entry(ref entry)
+                        if entry.enabled && !entry.source && entry.matches_release(release) =>
+                    {
+                        entry.as_source()
+                    }
+                    _ => continue,
+                };
+
+                let existing = list.lines.iter().position(|line| match line {
+                    SourceLine::Entry(entry) => {
+                        entry.source && entry.url == twin.url && entry.suite == twin.suite
+                    }
+                    _ => false,
+                });
+
+                match existing {
+                    Some(existing) => {
+                        if let SourceLine::Entry(ref mut entry) = list.lines[existing] {
+                            if entry.enabled || entry.locked {
+                                continue;
+                            }
+
+                            entry.enabled = true;
+                        }
+                    }
+                    None => {
+                        list.lines.insert(pos, SourceLine::Entry(twin));
+                        pos += 1;
+                    }
+                }
 
-        for entry in fs::read_dir("/etc/apt/sources.list.d/")? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map_or(false, |e| e == "list") {
-                paths.push(path);
+                add_modified(modified, id as u16);
+                count += 1;
             }
         }
 
-        Self::new_from_paths(paths.iter())
+        count
     }
 
-    /// When given a list of paths to source lists, this will attempt to parse them.
-    pub fn new_from_paths<P: AsRef<Path>, I: Iterator<Item = P>>(paths: I) -> SourceResult<Self> {
-        let files = paths.map(SourcesList::new).collect::<SourceResult<Vec<SourcesList>>>()?;
+    /// Enable or disable every entry on the `pocket` of a `release`, such as `disco-proposed`.
+    ///
+    /// Returns the number of entries which were changed. Locked entries are not enabled.
+    pub fn set_pocket_enabled(&mut self, release: &str, pocket: &str, enabled: bool) -> usize {
+        let suite = [release, "-", pocket].concat();
+        let mut count = 0;
+
+        self.entries_mut(|entry| {
+            if entry.suite != suite || entry.enabled == enabled || (enabled && entry.locked) {
+                return false;
+            }
+
+            entry.enabled = enabled;
+            count += 1;
+            true
+        });
 
-        Ok(SourcesLists { modified: Vec::with_capacity(files.len()), files })
+        count
     }
 
-    /// Specify to enable or disable a repo. `true` is returned if the repo was found.
-    pub fn repo_modify(&mut self, repo: &str, enabled: bool) -> bool {
-        let &mut Self { ref mut modified, ref mut files } = self;
+    /// Enables every entry on the `pocket` of a `release` as `set_pocket_enabled` does, and
+    /// synthesizes entries for the pocket on the official Ubuntu and Debian archives which
+    /// lack them.
+    ///
+    /// An entry is synthesized after each enabled entry of the base `release` whose URL has no
+    /// entry on the pocket yet, sharing its URL, options, and components. Other repositories
+    /// are left alone, as they may not serve the pocket. Returns the number of entries which
+    /// were changed or created.
+    pub fn enable_pocket_synthesizing(&mut self, release: &str, pocket: &str) -> usize {
+        let mut count = self.set_pocket_enabled(release, pocket, true);
 
-        let iterator = files
-            .iter_mut()
-            .enumerate()
-            .flat_map(|(pos, list)| list.get_entries_mut(repo).map(move |e| (pos, e)));
+        let suite = [release, "-", pocket].concat();
+        let found: HashSet<String> = self
+            .entries()
+            .filter(|entry| entry.suite == suite)
+            .map(|entry| entry.url().to_owned())
+            .collect();
 
-        let mut found = false;
-        for (pos, entry) in iterator {
-            add_modified(modified, pos as u16);
-            entry.enabled = enabled;
-            found = true;
+        let mut synthesized = Vec::new();
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+        for (id, list) in files.iter_mut().enumerate() {
+            let mut pos = 0;
+            while pos < list.lines.len() {
+                pos += 1;
+                let entry = match list.lines[pos - 1] {
+                    SourceLine::Entry(ref entry)
+                        if entry.enabled
+                            && entry.suite == release
+                            && !found.contains(entry.url())
+                            && is_official_archive(entry) =>
+                    {
+                        entry.with_suite(&suite)
+                    }
+                    _ => continue,
+                };
+
+                if synthesized.contains(&entry) {
+                    continue;
+                }
+
+                synthesized.push(entry.clone());
+                list.lines.insert(pos, SourceLine::Entry(entry));
+                add_modified(modified, id as u16);
+                count += 1;
+                pos += 1;
+            }
         }
 
-        found
+        count
     }
 
-    /// Constructs an iterator of enabled source entries from a sources list.
-    pub fn entries(&self) -> impl Iterator<Item = &SourceEntry> {
-        self.iter().flat_map(|list| list.lines.iter()).filter_map(move |entry| {
-            if let SourceLine::Entry(entry) = entry {
-                return Some(entry);
+    /// Re-renders every list canonically, marking those which changed textually as modified.
+    ///
+    /// Returns the number of lists which changed.
+    pub fn normalize(&mut self) -> usize {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let mut count = 0;
+        for (id, list) in files.iter_mut().enumerate() {
+            if list.normalize() {
+                add_modified(modified, id as u16);
+                count += 1;
             }
+        }
 
-            None
-        })
+        count
     }
 
-    /// A callback-based iterator that tracks which files have been modified.
-    pub fn entries_mut<F: FnMut(&mut SourceEntry) -> bool>(&mut self, mut func: F) {
-        let &mut Self { ref mut files, ref mut modified } = self;
-        for (pos, list) in files.iter_mut().enumerate() {
-            for entry in &mut list.lines {
-                if let SourceLine::Entry(entry) = entry {
-                    if func(entry) {
-                        add_modified(modified, pos as u16)
-                    }
-                }
+    /// Collapses consecutive identical comments in every list, marking those which changed as
+    /// modified.
+    ///
+    /// Returns the number of lists which changed.
+    pub fn dedup_comments(&mut self) -> usize {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+
+        let mut count = 0;
+        for (id, list) in files.iter_mut().enumerate() {
+            if list.dedup_comments() {
+                add_modified(modified, id as u16);
+                count += 1;
             }
         }
+
+        count
     }
 
-    /// Insert a source entry to the lists.
+    /// Splits the entries of every list into one line per component, marking those which
+    /// changed as modified.
     ///
-    /// If the entry already exists, it will be modified.
-    /// Otherwise, the entry will be added to the preferred list.
-    /// If the preferred list does not exist, it will be created.
-    pub fn insert_entry<P: AsRef<Path>>(
-        &mut self,
-        path: P,
-        entry: SourceEntry,
-    ) -> SourceResult<()> {
-        let path = path.as_ref();
-        let &mut Self { ref mut modified, ref mut files } = self;
+    /// Returns the number of lists which changed.
+    pub fn explode(&mut self) -> usize {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
 
+        let mut count = 0;
         for (id, list) in files.iter_mut().enumerate() {
-            if list.path == path {
-                match list.contains_entry(&entry.url) {
-                    Some(pos) => list.lines[pos] = SourceLine::Entry(entry),
-                    None => list.lines.push(SourceLine::Entry(entry)),
-                }
-
+            if list.explode() {
                 add_modified(modified, id as u16);
-                return Ok(());
+                count += 1;
             }
         }
 
-        files.push(SourcesList { path: path.to_path_buf(), lines: vec![SourceLine::Entry(entry)] });
+        count
+    }
+
+    /// Merges consecutive entries of every list which differ only in their components, marking
+    /// the lists which changed as modified. See `SourcesList::consolidate`.
+    ///
+    /// Returns the number of lists which changed.
+    pub fn consolidate(&mut self) -> usize {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
 
-        Ok(())
+        let mut count = 0;
+        for (id, list) in files.iter_mut().enumerate() {
+            if list.consolidate() {
+                add_modified(modified, id as u16);
+                count += 1;
+            }
+        }
+
+        count
     }
 
-    /// Remove the source entry from each file in the sources lists.
-    pub fn remove_entry(&mut self, repo: &str) {
-        let &mut Self { ref mut modified, ref mut files } = self;
+    /// Remove the source entry from each file in the sources lists, according to the `policy`.
+    ///
+    /// `RemovePolicy::Delete` removes the first entry of the repo in each file, whereas
+    /// `RemovePolicy::Disable` disables every enabled entry of the repo.
+    pub fn remove_entry(&mut self, repo: &str, policy: RemovePolicy) {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
         for (id, list) in files.iter_mut().enumerate() {
-            if let Some(line) = list.contains_entry(repo) {
-                list.lines.remove(line);
+            let changed = match policy {
+                RemovePolicy::Delete => match list.contains_entry(repo) {
+                    Some(line) => {
+                        list.lines.remove(line);
+                        true
+                    }
+                    None => false,
+                },
+                RemovePolicy::Disable => {
+                    let mut changed = false;
+                    for entry in list.get_entries_mut(repo).filter(|entry| entry.enabled) {
+                        entry.enabled = false;
+                        changed = true;
+                    }
+
+                    changed
+                }
+            };
+
+            if changed {
                 add_modified(modified, id as u16);
             }
         }
@@ -233,7 +1747,7 @@ impl SourcesLists {
     /// Changes are only applied in-memory. Use `SourcesLists::wirte_sync` to write
     /// all changes to the disk.
     pub fn dist_replace(&mut self, from_suite: &str, to_suite: &str) {
-        let &mut Self { ref mut modified, ref mut files } = self;
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
         for (id, file) in files.iter_mut().enumerate() {
             let mut changed = false;
             for line in &mut file.lines {
@@ -251,12 +1765,136 @@ impl SourcesLists {
         }
     }
 
+    /// Rename the base release of every entry on the `from_release`, preserving their pockets.
+    ///
+    /// `disco`, `disco-updates`, and `disco-security` will become `eoan`, `eoan-updates`, and
+    /// `eoan-security`. Returns the number of entries that were changed.
+    pub fn rename_release(&mut self, from_release: &str, to_release: &str) -> usize {
+        let mut count = 0;
+        self.entries_mut(|entry| {
+            if !entry.matches_release(from_release) {
+                return false;
+            }
+
+            entry.suite = match entry.pocket() {
+                Some(pocket) => [to_release, "-", pocket].concat(),
+                None => to_release.to_owned(),
+            };
+
+            count += 1;
+            true
+        });
+
+        count
+    }
+
+    /// Writes every list into `dir`, whether modified or not, leaving the lists on the system
+    /// untouched.
+    ///
+    /// The layout of the lists is mirrored from their closest common directory, so that lists
+    /// from **/etc/apt** are written as **$dir/sources.list** and **$dir/sources.list.d/**, which
+    /// can then be diffed against **/etc/apt** before being promoted.
+    ///
+    /// An error of the kind `io::ErrorKind::InvalidInput` is returned, before anything is
+    /// written, if a list has no file name, or the lists have no common directory which would
+    /// place every list within `dir`.
+    pub fn write_to_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let invalid = |path: &Path| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot place the list at '{}' within '{}'", path.display(), dir.display()),
+            )
+        };
+
+        let mut base = match self.files.first() {
+            Some(list) => list.path.parent().ok_or_else(|| invalid(&list.path))?.to_path_buf(),
+            None => return Ok(()),
+        };
+
+        for list in &self.files {
+            while !list.path.starts_with(&base) && base.pop() {}
+        }
+
+        let mut paths = Vec::with_capacity(self.files.len());
+        for list in &self.files {
+            let relative = list.path.strip_prefix(&base).map_err(|_| invalid(&list.path))?;
+            let contained = relative.file_name().is_some()
+                && relative.components().all(|component| match component {
+                    Component::Normal(_) => true,
+                    _ => false,
+                });
+
+            if !contained {
+                return Err(invalid(&list.path));
+            }
+
+            paths.push(dir.join(relative));
+        }
+
+        for (list, path) in self.files.iter().zip(paths) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(&path, list.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Rename the base release of every entry on the `from_release` as `rename_release` does,
+    /// and write the changed lists to disk.
+    ///
+    /// Each changed list is copied to "$path.save" before it is atomically replaced. If any list
+    /// fails to be written, the lists which were already written are restored from their
+    /// backups, and the in-memory lists are reverted.
+    pub fn rename_release_on_disk(
+        &mut self,
+        from_release: &str,
+        to_release: &str,
+    ) -> io::Result<()> {
+        let (files, modified) = (self.files.clone(), self.modified.clone());
+        self.rename_release(from_release, to_release);
+
+        let changed: Vec<usize> =
+            (0..files.len()).filter(|&id| self.files[id].lines != files[id].lines).collect();
+
+        let mut backups = Vec::new();
+        let result = changed.iter().try_for_each(|&id| {
+            let list = &mut self.files[id];
+            let backup = suffixed_path(&list.path, ".save");
+            fs::copy(&list.path, &backup)?;
+            backups.push((list.path.clone(), backup));
+            list.write_sync()
+        });
+
+        match result {
+            Ok(()) => {
+                self.modified = modified;
+                self.modified.retain(|&id| !changed.contains(&(id as usize)));
+                Ok(())
+            }
+            Err(why) => {
+                for (path, backup) in backups {
+                    if let Err(why) = fs::copy(&backup, &path) {
+                        eprintln!("failed to restore backup of {:?}: {}", backup, why);
+                    }
+                }
+
+                self.files = files;
+                self.modified = modified;
+                Err(why)
+            }
+        }
+    }
+
     /// Upgrade entries so that they point to a new release.
     ///
-    /// Files are copied to "$path.save" before being overwritten. On failure, these backup files
-    /// will be used to restore the original list.
+    /// Files are copied to "$path.save" before being overwritten, in the format that they were
+    /// parsed from. On failure, these backup files will be used to restore the original list.
     pub fn dist_upgrade(&mut self, retain: &HashSet<Box<str>>, from_suite: &str, to_suite: &str) -> io::Result<()> {
-        fn newfile(modified: &mut Vec<PathBuf>, path: &Path) -> io::Result<File> {
+        fn backup(modified: &mut Vec<PathBuf>, path: &Path) -> io::Result<()> {
             let backup_path = path
                 .file_name()
                 .map(|str| {
@@ -276,7 +1914,7 @@ impl SourcesLists {
 
             fs::copy(path, &backup_path)?;
             modified.push(backup_path);
-            fs::OpenOptions::new().truncate(true).write(true).open(path)
+            Ok(())
         }
 
         fn apply(
@@ -287,19 +1925,18 @@ impl SourcesLists {
             to_suite: &str,
         ) -> io::Result<()> {
             for list in sources.iter_mut() {
-                let mut current_file = newfile(modified, &list.path)?;
+                backup(modified, &list.path)?;
 
                 for line in list.lines.iter_mut() {
                     if let SourceLine::Entry(entry) = line {
-                        if !retain.contains(entry.url.as_str()) && entry.url.starts_with("http") && entry.suite.starts_with(from_suite) {
-                            entry.suite = entry.suite.replace(from_suite, to_suite);
+                        let upgraded = upgraded_entry(entry, retain, from_suite, to_suite);
+                        if let Some(upgraded) = upgraded {
+                            *entry = upgraded;
                         }
                     }
-
-                    writeln!(&mut current_file, "{}", line)?
                 }
 
-                current_file.flush()?;
+                list.write_sync()?;
             }
 
             Ok(())
@@ -329,30 +1966,203 @@ impl SourcesLists {
         from_suite: &'a str,
         to_suite: &'a str,
     ) -> impl Iterator<Item = String> + 'a {
-        self.entries().filter_map(move |entry| {
-            if entry.url.starts_with("http") && entry.suite.starts_with(from_suite) {
-                let entry = {
-                    let mut entry = entry.clone();
-                    entry.suite = entry.suite.replace(from_suite, to_suite);
-                    entry
-                };
+        self.entries()
+            .filter_map(move |entry| upgraded_entry(entry, &HashSet::new(), from_suite, to_suite))
+            .map(|entry| entry.dist_path())
+    }
 
-                let dist_path = entry.dist_path();
-                Some(dist_path)
-            } else {
-                None
-            }
-        })
+    /// Previews the entries that `dist_upgrade` would rewrite, without applying any changes.
+    ///
+    /// For each list with changes, the path of the list is returned with the before and after
+    /// state of every entry that would change. Entries whose URL is in `retain` are left out,
+    /// as `dist_upgrade` leaves them as they are.
+    pub fn dist_upgrade_preview(
+        &self,
+        retain: &HashSet<Box<str>>,
+        from_suite: &str,
+        to_suite: &str,
+    ) -> Vec<(PathBuf, Vec<(SourceEntry, SourceEntry)>)> {
+        self.iter()
+            .filter_map(|list| {
+                let changes: Vec<(SourceEntry, SourceEntry)> = list
+                    .lines
+                    .iter()
+                    .filter_map(|line| match line {
+                        SourceLine::Entry(entry) => {
+                            upgraded_entry(entry, retain, from_suite, to_suite)
+                                .map(|upgraded| (entry.clone(), upgraded))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
+                if changes.is_empty() {
+                    None
+                } else {
+                    Some((list.path.clone(), changes))
+                }
+            })
+            .collect()
+    }
+
+    /// Previews the lines that `dist_upgrade` would rewrite, as the path of each line's list,
+    /// and the line before and after the upgrade.
+    ///
+    /// This is `dist_upgrade_preview` in the form of a diff, for confirming the upgrade with
+    /// the user, and so it leaves out the same retained entries.
+    pub fn dist_upgrade_diff(
+        &self,
+        retain: &HashSet<Box<str>>,
+        from_suite: &str,
+        to_suite: &str,
+    ) -> Vec<(PathBuf, String, String)> {
+        self.dist_upgrade_preview(retain, from_suite, to_suite)
+            .into_iter()
+            .flat_map(|(path, changes)| {
+                changes.into_iter().map(move |(before, after)| {
+                    (path.clone(), before.to_string(), after.to_string())
+                })
+            })
+            .collect()
     }
 
     /// Overwrite all files which were modified.
-    pub fn write_sync(&mut self) -> io::Result<()> {
-        let &mut Self { ref mut modified, ref mut files } = self;
-        modified.drain(..).map(|id| files[id as usize].write_sync()).collect()
+    ///
+    /// Returns the paths of the files which were written, in the order that they were first
+    /// modified. If a file fails to be written, it and the files after it remain modified.
+    pub fn write_sync(&mut self) -> io::Result<Vec<PathBuf>> {
+        let &mut Self { ref mut modified, ref mut files, .. } = self;
+        let mut written = Vec::new();
+        while let Some(&id) = modified.first() {
+            let list = &mut files[id as usize];
+            list.write_sync()?;
+            written.push(list.path.clone());
+            modified.remove(0);
+        }
+
+        Ok(written)
+    }
+}
+
+/// Whether the entry points to the Ubuntu or Debian archive, which serve every pocket of a
+/// release.
+fn is_official_archive(entry: &SourceEntry) -> bool {
+    match entry.archive_kind() {
+        ArchiveKind::Ubuntu | ArchiveKind::Debian => true,
+        ArchiveKind::Launchpad | ArchiveKind::ThirdParty => false,
+    }
+}
+
+/// Whether `dist_upgrade` rewrites entries with the scheme of this entry's URL.
+///
+/// Mirrors over HTTP and local mirrors follow the new release, but media such as `cdrom:` do not.
+fn upgrades_scheme(entry: &SourceEntry) -> bool {
+    ["http", "https", "file"].contains(&entry.scheme())
+}
+
+/// The entry after it has been upgraded from the `from_suite` to the `to_suite`, if
+/// `dist_upgrade` would upgrade it, rather than retain it.
+fn upgraded_entry(
+    entry: &SourceEntry,
+    retain: &HashSet<Box<str>>,
+    from_suite: &str,
+    to_suite: &str,
+) -> Option<SourceEntry> {
+    if !retain.contains(entry.url.as_str())
+        && upgrades_scheme(entry)
+        && entry.suite.starts_with(from_suite)
+    {
+        Some(entry.with_suite(&entry.suite.replace(from_suite, to_suite)))
+    } else {
+        None
+    }
+}
+
+/// The `path` with the `suffix` appended, such as for the temporary file or backup of a list.
+/// Gives `file` the permissions of the list at `path`, or `0644` if the list does not exist yet,
+/// so that apt can read it when it drops its privileges.
+#[cfg(unix)]
+fn keep_permissions(path: &Path, file: &File) -> io::Result<()> {
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(_) => fs::Permissions::from_mode(0o644),
+    };
+
+    file.set_permissions(permissions)
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// An entry's kind, URL, suite, components, and options, in a form which compares equal for
+/// entries which apt would treat the same.
+pub(crate) type SemanticKey = (bool, String, String, Vec<String>, Vec<String>);
+
+pub(crate) fn semantic_key(entry: &SourceEntry) -> SemanticKey {
+    let mut entry = entry.clone();
+    entry.dedup_options();
+
+    let mut options: Vec<String> = entry.option_tokens().into_iter().map(String::from).collect();
+    options.sort();
+
+    let mut components = entry.components.clone();
+    components.sort();
+    components.dedup();
+
+    (entry.source, entry.url().to_owned(), entry.suite.clone(), components, options)
+}
+
+/// Collects the path of the main sources list, and each `.list` and `.sources` file in the
+/// directory of additional lists.
+fn list_paths(conf: &AptConfig) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![conf.sourcelist.clone()];
+
+    for entry in fs::read_dir(&conf.sourceparts)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().map_or(false, |e| e == "list" || e == "sources") {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Finds a list which includes itself through the given `(included, includer)` edges.
+fn include_cycle(edges: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    fn visit<'a>(
+        path: &'a Path,
+        edges: &'a [(PathBuf, PathBuf)],
+        stack: &mut Vec<&'a Path>,
+        done: &mut HashSet<&'a Path>,
+    ) -> Option<PathBuf> {
+        if stack.contains(&path) {
+            return Some(path.to_path_buf());
+        }
+
+        if !done.insert(path) {
+            return None;
+        }
+
+        stack.push(path);
+        for (included, _) in edges.iter().filter(|(_, includer)| includer == path) {
+            if let Some(cycle) = visit(included, edges, stack, done) {
+                return Some(cycle);
+            }
+        }
+
+        stack.pop();
+        None
     }
+
+    let mut done = HashSet::new();
+    edges.iter().find_map(|(_, includer)| visit(includer, edges, &mut Vec::new(), &mut done))
 }
 
-fn add_modified(modified: &mut Vec<u16>, list: u16) {
+pub(crate) fn add_modified(modified: &mut Vec<u16>, list: u16) {
     if !modified.iter().any(|&v| v == list) {
         modified.push(list);
     }
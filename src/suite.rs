@@ -0,0 +1,59 @@
+use super::*;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A validated suite, such as `disco` or `disco-security`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Suite(String);
+
+impl FromStr for Suite {
+    type Err = SourceError;
+    fn from_str(suite: &str) -> Result<Self, Self::Err> {
+        let valid = !suite.is_empty()
+            && !suite.starts_with('-')
+            && !suite.ends_with('-')
+            && suite.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+        if !valid {
+            return Err(SourceError::InvalidValue { field: "suite", value: suite.to_owned() });
+        }
+
+        Ok(Suite(suite.to_owned()))
+    }
+}
+
+impl Display for Suite {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Suite {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Suite {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The base release of the suite, such as `disco` for `disco-security`.
+    pub fn release(&self) -> &str {
+        split_suite(&self.0).0
+    }
+
+    /// The pocket of the suite, such as `security` for `disco-security`.
+    pub fn pocket(&self) -> Option<&str> {
+        split_suite(&self.0).1
+    }
+}
+
+/// Splits a suite into its base release and pocket.
+pub(crate) fn split_suite(suite: &str) -> (&str, Option<&str>) {
+    match suite.find('-') {
+        Some(pos) => (&suite[..pos], Some(&suite[pos + 1..])),
+        None => (suite, None),
+    }
+}
@@ -1,4 +1,6 @@
 pub use super::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 const SOURCE_LIST: &str = r#"
@@ -29,23 +31,24 @@ const POP_PPA_DISABLED: &str = r#"
 "#;
 
 fn sources_lists() -> SourcesLists {
-    SourcesLists {
-        modified: Vec::new(),
-        files: vec![
-            SOURCE_LIST.parse::<SourcesList>().expect("source list gen"),
-            POP_PPA.parse::<SourcesList>().expect("pop ppa gen")
-        ]
-    }
+    SourcesLists::from_lists(vec![
+        SOURCE_LIST.parse::<SourcesList>().expect("source list gen"),
+        POP_PPA.parse::<SourcesList>().expect("pop ppa gen"),
+    ])
 }
 
 fn sources_lists_pop_disabled() -> SourcesLists {
-    SourcesLists {
-        modified: Vec::new(),
-        files: vec![
-            SOURCE_LIST.parse::<SourcesList>().expect("source list gen"),
-            POP_PPA_DISABLED.parse::<SourcesList>().expect("pop ppa gen")
-        ]
-    }
+    SourcesLists::from_lists(vec![
+        SOURCE_LIST.parse::<SourcesList>().expect("source list gen"),
+        POP_PPA_DISABLED.parse::<SourcesList>().expect("pop ppa gen"),
+    ])
+}
+
+/// Parses a list as though it was read from the `path`.
+fn list_at<P: AsRef<Path>>(path: P, text: &str) -> SourcesList {
+    let mut list = text.parse::<SourcesList>().expect("list gen");
+    list.path = path.as_ref().to_path_buf();
+    list
 }
 
 #[test]
@@ -84,6 +87,9 @@ fn binary() {
         .unwrap(),
         SourceLine::Entry(SourceEntry {
             enabled: true,
+            locked: false,
+            comment: None,
+            comment_spacing: None,
             source: false,
             url: "http://us.archive.ubuntu.com/ubuntu/".into(),
             suite: "cosmic".into(),
@@ -108,6 +114,9 @@ fn source() {
         .unwrap(),
         SourceLine::Entry(SourceEntry {
             enabled: true,
+            locked: false,
+            comment: None,
+            comment_spacing: None,
             source: true,
             url: "http://us.archive.ubuntu.com/ubuntu/".into(),
             suite: "cosmic".into(),
@@ -124,13 +133,42 @@ fn source() {
 
 #[test]
 fn fluff() {
-    let comment = "# deb-src http://us.archive.ubuntu.com/ubuntu/ cosmic main \
-                   restricted universe multiverse";
+    let comment = "# See http://help.ubuntu.com/community/UpgradeNotes for how to upgrade";
     assert_eq!(SourceLine::from_str(comment).unwrap(), SourceLine::Comment(comment.into()));
 
     assert_eq!(SourceLine::from_str("").unwrap(), SourceLine::Empty);
 }
 
+#[test]
+fn disabled() {
+    let line = "# deb-src http://us.archive.ubuntu.com/ubuntu/ cosmic main \
+                restricted universe multiverse";
+    assert_eq!(
+        SourceLine::from_str(line).unwrap(),
+        SourceLine::Entry(SourceEntry {
+            enabled: false,
+            locked: false,
+            comment: None,
+            comment_spacing: None,
+            source: true,
+            url: "http://us.archive.ubuntu.com/ubuntu/".into(),
+            suite: "cosmic".into(),
+            options: None,
+            components: vec![
+                "main".into(),
+                "restricted".into(),
+                "universe".into(),
+                "multiverse".into(),
+            ]
+        })
+    );
+
+    let lists = sources_lists();
+    assert_eq!(lists.entries().count(), 15);
+    assert_eq!(lists.active_entries().count(), 13);
+    assert!(lists.active_entries().all(|entry| entry.enabled));
+}
+
 #[test]
 fn options() {
     let options = [
@@ -146,6 +184,9 @@ fn options() {
             SourceLine::from_str(source).unwrap(),
             SourceLine::Entry(SourceEntry {
                 enabled: true,
+                locked: false,
+                comment: None,
+                comment_spacing: None,
                 source: false,
                 url: "http://apt.pop-os.org/proprietary".into(),
                 suite: "cosmic".into(),
@@ -167,6 +208,9 @@ fn options() {
             SourceLine::from_str(source).unwrap(),
             SourceLine::Entry(SourceEntry {
                 enabled: true,
+                locked: false,
+                comment: None,
+                comment_spacing: None,
                 source: false,
                 url: "https://deb.termius.com".into(),
                 suite: "squeeze".into(),
@@ -176,3 +220,2468 @@ fn options() {
         )
     }
 }
+
+#[test]
+fn suite_clone() {
+    let mut lists = sources_lists();
+
+    let repo = "http://apt.pop-os.org/proprietary";
+    assert!(lists.insert_suite_clone(repo, "disco", "disco-backports"));
+    assert!(!lists.insert_suite_clone(repo, "disco", "disco-backports"));
+
+    let lines = &lists[0].lines;
+    let pos = lines
+        .iter()
+        .position(|line| line.to_string() == "deb http://apt.pop-os.org/proprietary disco main")
+        .expect("failed to find proprietary PPA");
+
+    assert_eq!(
+        "deb http://apt.pop-os.org/proprietary disco-backports main",
+        &lines[pos + 1].to_string()
+    );
+}
+
+#[test]
+fn source_component_mismatch() {
+    let list = "deb http://apt.pop-os.org/proprietary disco main restricted\n\
+                deb-src http://apt.pop-os.org/proprietary disco restricted main\n\
+                deb http://ppa.launchpad.net/system76/pop/ubuntu disco main universe\n\
+                deb-src http://ppa.launchpad.net/system76/pop/ubuntu disco main\n";
+
+    let lists =
+        SourcesLists::from_lists(vec![list.parse::<SourcesList>().expect("source list gen")]);
+
+    assert_eq!(
+        lists.validate(),
+        vec![SourceWarning::SourceComponentMismatch {
+            url: "http://ppa.launchpad.net/system76/pop/ubuntu".into(),
+            suite: "disco".into(),
+            binary_components: vec!["main".into(), "universe".into()],
+            source_components: vec!["main".into()],
+        }]
+    );
+
+    assert!(sources_lists().validate().is_empty());
+}
+
+#[test]
+fn pin_hints() {
+    let entry = "deb [arch=amd64 target-release=disco-backports pin=origin] http://x/ubuntu disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+
+    assert_eq!(entry.option("arch"), Some("amd64"));
+    assert_eq!(entry.target_release(), Some("disco-backports"));
+    assert_eq!(entry.pin_hints(), vec![("target-release", "disco-backports"), ("pin", "origin")]);
+}
+
+#[test]
+fn rename_release() {
+    let mut lists = sources_lists();
+
+    assert_eq!(lists.rename_release("disco", "eoan"), 14);
+    assert!(lists.active_entries().all(|entry| entry.release() == "eoan"));
+    assert!(lists.active_entries().any(|entry| entry.suite == "eoan-security"));
+    assert_eq!(lists.modified, vec![0, 1]);
+}
+
+#[test]
+fn sources_list_from_bytes() {
+    use std::convert::TryFrom;
+
+    let list = SourcesList::try_from(POP_PPA.as_bytes()).expect("pop ppa gen");
+    assert_eq!(list.lines, POP_PPA.parse::<SourcesList>().unwrap().lines);
+
+    match SourcesList::try_from(&b"deb http://x/ubuntu \xff main"[..]) {
+        Err(SourcesListError::InvalidUtf8 { offset, .. }) => assert_eq!(offset, 20),
+        other => panic!("expected invalid UTF-8 error, found {:?}", other),
+    }
+}
+
+#[test]
+fn redundant_trailing_slash() {
+    let list = "deb http://us.archive.ubuntu.com/ubuntu disco main\n\
+                deb http://us.archive.ubuntu.com/ubuntu/ disco-updates main\n";
+
+    let lists =
+        SourcesLists::from_lists(vec![list.parse::<SourcesList>().expect("source list gen")]);
+
+    assert_eq!(
+        lists.validate(),
+        vec![SourceWarning::RedundantTrailingSlash {
+            a: "http://us.archive.ubuntu.com/ubuntu".into(),
+            b: "http://us.archive.ubuntu.com/ubuntu/".into(),
+            path: PathBuf::new(),
+        }]
+    );
+}
+
+#[test]
+fn insert_with_comment() {
+    let mut lists = sources_lists();
+    let entry = "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+
+    lists[1].path = PathBuf::from("pop.list");
+    lists.insert_entry_with_comment("pop.list", "Pop!_OS PPA", entry.clone()).unwrap();
+    lists.insert_entry_with_comment("pop.list", "System76 PPA", entry).unwrap();
+
+    let list = lists[1].to_string();
+    assert_eq!(
+        list,
+        "\n#: System76 PPA\ndeb http://ppa.launchpad.net/system76/pop/ubuntu disco main\n\
+         deb-src http://ppa.launchpad.net/system76/pop/ubuntu disco main\n"
+    );
+
+    assert_eq!(list.parse::<SourcesList>().unwrap().to_string(), list);
+
+    // Other entries of the repo, and comments which were not written here, are kept.
+    let header = POP_PPA.replacen('\n', "\n# Header of the list\n", 1);
+    let mut lists = SourcesLists::from_lists(vec![list_at("pop.list", &header)]);
+
+    let entry = "deb-src http://ppa.launchpad.net/system76/pop/ubuntu eoan main";
+    lists.insert_entry_with_comment("pop.list", "Pop!_OS", entry.parse().unwrap()).unwrap();
+    let entry = "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main universe";
+    lists.insert_entry_with_comment("pop.list", "Pop!_OS", entry.parse().unwrap()).unwrap();
+    assert_eq!(
+        lists[0].to_string(),
+        "\n# Header of the list\n#: Pop!_OS\n\
+         deb http://ppa.launchpad.net/system76/pop/ubuntu disco main universe\n\
+         deb-src http://ppa.launchpad.net/system76/pop/ubuntu disco main\n\
+         #: Pop!_OS\ndeb-src http://ppa.launchpad.net/system76/pop/ubuntu eoan main\n"
+    );
+
+    // Inserting again from freshly parsed lists replaces the comment rather than repeating it,
+    // as does a comment of the same text which was not written by this method.
+    let entry = "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main";
+    let mut text = ["# Pop!_OS\n", entry, "\n"].concat();
+    for _ in 0..3 {
+        let mut lists = SourcesLists::from_lists(vec![list_at("pop.list", &text)]);
+        lists.insert_entry_with_comment("pop.list", "Pop!_OS", entry.parse().unwrap()).unwrap();
+        text = lists[0].to_string();
+    }
+
+    assert_eq!(text, ["#: Pop!_OS\n", entry, "\n"].concat());
+}
+
+#[test]
+fn primary_mirror() {
+    assert_eq!(sources_lists().primary_mirror(), Some("us.archive.ubuntu.com"));
+}
+
+#[test]
+fn pocket_enabled() {
+    let mut lists = sources_lists();
+
+    assert_eq!(lists.set_pocket_enabled("disco", "proposed", false), 2);
+    assert_eq!(lists.set_pocket_enabled("disco", "proposed", false), 0);
+    assert!(lists.active_entries().all(|entry| entry.pocket() != Some("proposed")));
+
+    assert_eq!(lists.set_pocket_enabled("disco", "proposed", true), 2);
+    assert_eq!(lists.set_pocket_enabled("disco", "security", true), 0);
+
+    // Without any backports entries, they are only synthesized when asked to, and only from
+    // the release entries of the official archive.
+    let mut lists = sources_lists();
+    lists.files.truncate(1);
+    lists.files[0].lines.retain(|line| match line {
+        SourceLine::Entry(entry) => entry.suite != "disco-backports",
+        _ => true,
+    });
+
+    assert_eq!(lists.set_pocket_enabled("disco", "backports", true), 0);
+    assert_eq!(lists.enable_pocket_synthesizing("disco", "backports"), 2);
+    let backports: Vec<&str> = lists
+        .active_entries()
+        .filter(|entry| entry.suite == "disco-backports")
+        .map(|entry| entry.url())
+        .collect();
+    assert_eq!(backports, vec!["http://us.archive.ubuntu.com/ubuntu"; 2]);
+    assert_eq!(lists.enable_pocket_synthesizing("disco", "backports"), 0);
+
+    // Pockets are synthesized for each archive which lacks them, even if another has them.
+    let list = "deb http://archive.ubuntu.com/ubuntu disco main\n\
+                deb http://archive.ubuntu.com/ubuntu disco-updates main\n\
+                deb http://deb.debian.org/debian disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    assert_eq!(lists.enable_pocket_synthesizing("disco", "updates"), 1);
+    assert_eq!(
+        lists[0].lines[3],
+        SourceLine::Entry("deb http://deb.debian.org/debian disco-updates main".parse().unwrap())
+    );
+}
+
+#[test]
+fn crlf() {
+    let input = POP_PPA_DISABLED.replace('\n', "\r\n");
+    let list = input.parse::<SourcesList>().expect("crlf gen");
+
+    assert!(list.crlf);
+    assert!(list.lines.iter().all(|line| !line.to_string().contains('\r')));
+    assert_eq!(list.to_string(), input);
+
+    let mut list = list;
+    list.crlf = false;
+    assert_eq!(list.to_string(), POP_PPA_DISABLED);
+}
+
+#[test]
+fn components_str() {
+    let mut entry =
+        "deb http://apt.pop-os.org/proprietary disco main".parse::<SourceEntry>().unwrap();
+
+    entry.set_components_str("  main\trestricted  universe ");
+    assert_eq!(entry.components, vec!["main", "restricted", "universe"]);
+    assert_eq!(entry.components_str(), "main restricted universe");
+}
+
+/// Creates an empty directory within the system's temporary directory for a test to use.
+fn test_dir(name: &str) -> PathBuf {
+    let name = format!("apt-sources-lists-{}-{}", name, std::process::id());
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("failed to create test directory");
+    dir
+}
+
+#[test]
+fn scan_backups() {
+    let root = test_dir("scan-backups");
+    let parts = root.join("etc/apt/sources.list.d");
+    std::fs::create_dir_all(&parts).unwrap();
+
+    std::fs::write(root.join("etc/apt/sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list.distUpgrade"), SOURCE_LIST).unwrap();
+    std::fs::write(parts.join("pop.list.save"), [POP_PPA, "not a source\n"].concat()).unwrap();
+    std::fs::write(parts.join("pop.list"), POP_PPA).unwrap();
+
+    // Backups of other files are ignored, and lists which are not UTF-8 are skipped.
+    std::fs::write(root.join("etc/apt/trusted.gpg.save"), [0x99, 0xff, 0x00]).unwrap();
+    std::fs::write(parts.join("broken.list.save"), [0x99, 0xff, 0x00]).unwrap();
+
+    let backups = SourcesLists::scan_backups(&root).expect("failed to scan backups");
+    let paths: Vec<&Path> = backups.iter().map(|(path, _)| path.as_path()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            root.join("etc/apt/sources.list.d/pop.list.save"),
+            root.join("etc/apt/sources.list.distUpgrade"),
+        ]
+    );
+
+    assert_eq!(backups[0].1.lines.last(), Some(&SourceLine::Invalid("not a source".into())));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn suite() {
+    let suite = "disco-security".parse::<Suite>().unwrap();
+    assert_eq!(suite.release(), "disco");
+    assert_eq!(suite.pocket(), Some("security"));
+    assert_eq!(suite.to_string(), "disco-security");
+
+    let suite = "disco".parse::<Suite>().unwrap();
+    assert_eq!(suite.release(), "disco");
+    assert_eq!(suite.pocket(), None);
+
+    for invalid in &["", "Disco", "disco-", "-disco", "disco security", "disco/updates"] {
+        assert!(invalid.parse::<Suite>().is_err(), "{} should be invalid", invalid);
+    }
+}
+
+#[test]
+fn owned_entries() {
+    let lists = sources_lists();
+    let cloned = lists.cloned_entries();
+
+    assert_eq!(cloned.len(), 15);
+    assert_eq!(lists.into_entries(), cloned);
+}
+
+#[test]
+fn normalize() {
+    let options = [
+        "deb [ arch=amd64 ] http://apt.pop-os.org/proprietary cosmic main",
+        "deb [arch=amd64 ] http://apt.pop-os.org/proprietary cosmic main",
+        "deb [ arch=amd64] http://apt.pop-os.org/proprietary cosmic main",
+        "deb [arch=amd64]http://apt.pop-os.org/proprietary cosmic main",
+        "deb [ arch=amd64 ]http://apt.pop-os.org/proprietary cosmic main",
+    ];
+
+    for source in &options {
+        let mut list = source.parse::<SourcesList>().unwrap();
+        assert!(list.normalize());
+        assert!(!list.normalize());
+        assert_eq!(
+            list.to_string(),
+            "deb [arch=amd64] http://apt.pop-os.org/proprietary cosmic main\n"
+        );
+    }
+
+    // Only the first list changes, as its duplicate empty line was dropped when parsing.
+    let mut lists = sources_lists();
+    assert_eq!(lists.normalize(), 1);
+    assert_eq!(lists.modified, vec![0]);
+}
+
+#[test]
+fn scan_filtered() {
+    let root = test_dir("scan-filtered");
+    let parts = root.join("etc/apt/sources.list.d");
+    std::fs::create_dir_all(&parts).unwrap();
+
+    std::fs::write(root.join("etc/apt/sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(parts.join("myapp-pop.list"), POP_PPA).unwrap();
+    std::fs::write(parts.join("other.list"), "this list is not valid\n").unwrap();
+
+    let lists = SourcesLists::scan_filtered(&root, |path| match path.file_name() {
+        Some(name) => name.to_string_lossy().starts_with("myapp-"),
+        None => false,
+    })
+    .expect("failed to scan");
+
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].path, parts.join("myapp-pop.list"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn scan_since() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let root = test_dir("scan-since");
+    let parts = root.join("etc/apt/sources.list.d");
+    std::fs::create_dir_all(&parts).unwrap();
+
+    std::fs::write(root.join("etc/apt/sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(parts.join("pop.list"), POP_PPA).unwrap();
+
+    let (lists, skipped) = SourcesLists::scan_since(&root, UNIX_EPOCH).expect("failed to scan");
+    assert_eq!(lists.len(), 2);
+    assert!(skipped.is_empty());
+
+    let future = SystemTime::now() + Duration::from_secs(3600);
+    let (lists, skipped) = SourcesLists::scan_since(&root, future).expect("failed to scan");
+    assert!(lists.is_empty());
+    assert_eq!(skipped.len(), 2);
+
+    // A list whose modification time equals `since` has not changed since then.
+    let since = std::fs::metadata(parts.join("pop.list")).unwrap().modified().unwrap();
+    let (lists, skipped) = SourcesLists::scan_since(&root, since).expect("failed to scan");
+    assert!(skipped.contains(&parts.join("pop.list")));
+    assert!(lists.iter().all(|list| list.path != parts.join("pop.list")));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn new_from_paths_lenient() {
+    let dir = test_dir("new-from-paths-lenient");
+    let paths = [dir.join("good.list"), dir.join("bad.list"), dir.join("missing.list")];
+    std::fs::write(&paths[0], POP_PPA).unwrap();
+    std::fs::write(&paths[1], "this list is not valid\n").unwrap();
+
+    let (lists, errors) = SourcesLists::new_from_paths_lenient(paths.iter());
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].path, paths[0]);
+
+    assert_eq!(errors.len(), 2);
+    match errors[0] {
+        (ref path, SourceError::SourcesList { .. }) => assert_eq!(*path, paths[1]),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    match errors[1] {
+        (ref path, SourceError::SourcesListOpen { .. }) => assert_eq!(*path, paths[2]),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_components() {
+    match "deb http://us.archive.ubuntu.com/ubuntu/ disco".parse::<SourceEntry>() {
+        Err(SourceError::MissingField { field }) => assert_eq!(field, "components"),
+        other => panic!("expected missing components error, found {:?}", other),
+    }
+
+    let flat = "deb [trusted=yes] file:/srv/repo ./".parse::<SourceEntry>().unwrap();
+    assert_eq!(flat.suite, "./");
+    assert!(flat.components.is_empty());
+}
+
+#[test]
+fn flat_repository() {
+    let line = "deb [trusted=yes] file:///srv/repo/ ./";
+    let flat = line.parse::<SourceEntry>().unwrap();
+
+    assert!(flat.is_flat());
+    assert_eq!(flat.to_string(), line);
+    assert_eq!(flat.dist_path(), "file:///srv/repo");
+    assert_eq!(flat.release_url(), "file:///srv/repo/InRelease");
+    assert_eq!(flat.dist_components().count(), 0);
+
+    let nested = "deb http://example.com/repo amd64/".parse::<SourceEntry>().unwrap();
+    assert_eq!(nested.dist_path_get("Packages"), "http://example.com/repo/amd64/Packages");
+
+    let entry = "deb http://apt.pop-os.org/proprietary disco main".parse::<SourceEntry>().unwrap();
+    assert!(!entry.is_flat());
+    assert_eq!(entry.release_url(), "http://apt.pop-os.org/proprietary/dists/disco/InRelease");
+}
+
+#[test]
+fn file_urls() {
+    let scheme = |line: &str| line.parse::<SourceEntry>().unwrap().scheme().to_owned();
+    assert_eq!(scheme("deb http://x/ubuntu disco main"), "http");
+    assert_eq!(scheme("deb https://x/ubuntu disco main"), "https");
+    assert_eq!(scheme("deb file:///srv/mirror/ubuntu disco main"), "file");
+    assert_eq!(scheme("deb cdrom:[Pop_OS 19.04]/ disco main"), "cdrom");
+    assert_eq!(scheme("deb /srv/mirror/ubuntu disco main"), "");
+
+    let entry = "deb file:///srv//mirror/ubuntu/ disco main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.dist_path(), "file:///srv/mirror/ubuntu/dists/disco");
+    assert_eq!(entry.pool_path(), "file:///srv/mirror/ubuntu/pool/");
+    assert_eq!(
+        entry.dist_components().collect::<Vec<_>>(),
+        vec!["file:///srv/mirror/ubuntu/dists/disco/main".to_owned()]
+    );
+
+    let entry = "deb file:/srv/mirror/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.dist_path(), "file:///srv/mirror/ubuntu/dists/disco");
+
+    let lists = SourcesLists::from_lists(vec![
+        "deb file:///srv/mirror/ubuntu disco main\ndeb cdrom:[Pop_OS 19.04]/ disco main\n"
+            .parse()
+            .unwrap(),
+    ]);
+
+    let diff = lists.dist_upgrade_diff(&HashSet::new(), "disco", "eoan");
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff[0].2, "deb file:///srv/mirror/ubuntu eoan main");
+}
+
+#[test]
+fn strict_urls() {
+    for line in &[
+        "deb http://x/ubuntu disco main",
+        "deb [arch=amd64] https://x/ubuntu disco main",
+        "deb ftp://x/debian buster main",
+        "deb file:/srv/repo ./",
+        "deb cdrom:[Pop_OS 19.04]/ disco main",
+        "deb mirror://mirrors.ubuntu.com/mirrors.txt disco main",
+        "deb mirror+file:/etc/apt/mirrors.txt disco main",
+        "deb mirror+http://x/mirrors.txt disco main",
+        "deb tor+https://x/ubuntu disco main",
+        "deb tor+mirror+https://x/mirrors.txt disco main",
+    ] {
+        assert!(SourceEntry::from_str_validated(line).is_ok(), "{} should be valid", line);
+    }
+
+    for &(line, url) in &[
+        ("deb htp://x/ubuntu disco main", "htp://x/ubuntu"),
+        ("deb archive.ubuntu.com/ubuntu disco main", "archive.ubuntu.com/ubuntu"),
+        ("deb tor+htp://x/ubuntu disco main", "tor+htp://x/ubuntu"),
+        ("deb http+tor://x/ubuntu disco main", "http+tor://x/ubuntu"),
+        ("deb mirror+://x/ubuntu disco main", "mirror+://x/ubuntu"),
+    ] {
+        assert!(line.parse::<SourceEntry>().is_ok());
+        match SourceEntry::from_str_validated(line) {
+            Err(SourceError::InvalidValue { field: "url", value }) => assert_eq!(value, url),
+            other => panic!("expected an invalid url, found {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn find_duplicates() {
+    let list = |path: &str, text: &str| {
+        let mut list = text.parse::<SourcesList>().unwrap();
+        list.path = PathBuf::from(path);
+        list
+    };
+
+    let lists = SourcesLists::from_lists(vec![
+        list(
+            "/etc/apt/sources.list",
+            "deb http://x/ubuntu disco main universe\n\
+             deb [arch=amd64,i386] http://y/ubuntu disco main\n\
+             deb-src http://x/ubuntu disco main\n",
+        ),
+        list(
+            "/etc/apt/sources.list.d/x.list",
+            "deb http://x/ubuntu/ disco universe main\n\
+             deb [arch=i386,amd64] http://y/ubuntu disco main\n\
+             deb [arch=amd64] http://y/ubuntu disco main\n\
+             # deb-src http://x/ubuntu disco main\n",
+        ),
+    ]);
+
+    let paths = vec![
+        PathBuf::from("/etc/apt/sources.list"),
+        PathBuf::from("/etc/apt/sources.list.d/x.list"),
+    ];
+
+    let duplicates = lists.find_duplicates();
+    assert_eq!(duplicates.len(), 2);
+    assert_eq!(duplicates[0].0.to_string(), "deb http://x/ubuntu disco main universe");
+    assert_eq!(duplicates[0].1, paths);
+    assert_eq!(duplicates[1].0.to_string(), "deb [arch=amd64,i386] http://y/ubuntu disco main");
+    assert_eq!(duplicates[1].1, paths);
+
+    assert!(sources_lists().find_duplicates().is_empty());
+}
+
+#[test]
+fn transform_file() {
+    let dir = test_dir("transform-file");
+    let path = dir.join("pop.list");
+    std::fs::write(&path, POP_PPA).unwrap();
+
+    SourcesList::transform_file(&path, |line| match line {
+        SourceLine::Entry(ref entry) if entry.source => None,
+        SourceLine::Entry(entry) => Some(SourceLine::Entry(entry.with_suite("eoan"))),
+        line => Some(line),
+    })
+    .expect("failed to transform file");
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "\ndeb http://ppa.launchpad.net/system76/pop/ubuntu eoan main\n"
+    );
+
+    std::fs::write(&path, "deb http://x/ubuntu\n").unwrap();
+    assert!(SourcesList::transform_file(&path, Some).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "deb http://x/ubuntu\n");
+    assert!(!dir.join("pop.list.tmp").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn repo_enabled() {
+    let mut lists = sources_lists();
+    let repo = "http://apt.pop-os.org/proprietary";
+
+    assert_eq!(lists.is_repo_enabled(repo, "disco"), Some(true));
+    assert_eq!(lists.is_repo_enabled(repo, "eoan"), None);
+
+    lists.repo_modify(repo, false);
+    assert_eq!(lists.is_repo_enabled(repo, "disco"), Some(false));
+}
+
+#[test]
+fn add_component() {
+    let mut lists = sources_lists();
+    let repo = "http://apt.pop-os.org/proprietary";
+
+    assert!(lists.add_component_to(repo, "disco", "non-free"));
+    assert!(!lists.add_component_to(repo, "disco", "non-free"));
+    assert!(!lists.add_component_to(repo, "eoan", "non-free"));
+    assert_eq!(lists.modified, vec![0]);
+
+    let entry = lists.entries().find(|e| e.url == repo).expect("failed to find proprietary PPA");
+    assert_eq!(entry.components, vec!["main", "non-free"]);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn serde_round_trip() {
+    let list = &sources_lists()[1];
+    let json = serde_json::to_string(list).unwrap();
+    let parsed: SourcesList = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.lines, list.lines);
+    assert_eq!(parsed.to_string(), list.to_string());
+
+    let line = SourceLine::Comment("# comment".into());
+    let json = serde_json::to_string(&line).unwrap();
+    assert_eq!(json, r##"{"type":"Comment","value":"# comment"}"##);
+    assert_eq!(serde_json::from_str::<SourceLine>(&json).unwrap(), line);
+    assert_eq!(serde_json::to_string(&SourceLine::Empty).unwrap(), r#"{"type":"Empty"}"#);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn jsonl() {
+    let mut lists = sources_lists();
+    lists[1].path = PathBuf::from("pop.list");
+
+    let jsonl = lists.to_jsonl();
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(lines.len(), 13);
+    assert_eq!(
+        lines[12],
+        r#"{"path":"pop.list","url":"http://ppa.launchpad.net/system76/pop/ubuntu","suite":"disco","components":["main"],"options":null,"enabled":true,"source":true}"#
+    );
+}
+
+#[test]
+fn https_upgrade() {
+    let entry = SourceEntry::from_str("deb http://archive.ubuntu.com/ubuntu/ disco main");
+    assert_eq!(
+        entry.unwrap().https_upgrade_candidate().unwrap().to_string(),
+        "deb https://archive.ubuntu.com/ubuntu/ disco main"
+    );
+
+    // Country mirrors are run by third parties, which may not serve HTTPS.
+    let entry = SourceEntry::from_str("deb http://us.archive.ubuntu.com/ubuntu/ disco main");
+    assert_eq!(entry.unwrap().https_upgrade_candidate(), None);
+
+    let entry = SourceEntry::from_str("deb http://notarchive.ubuntu.com/ubuntu/ disco main");
+    assert_eq!(entry.unwrap().https_upgrade_candidate(), None);
+
+    let mut lists = sources_lists();
+    assert_eq!(lists.upgrade_http_to_https(), 4);
+    assert!(lists
+        .entries()
+        .filter(|entry| entry.url.contains("us.archive.ubuntu.com"))
+        .all(|entry| entry.url.starts_with("http://")));
+}
+
+#[test]
+fn signature() {
+    let dir = test_dir("signature");
+    let keyring = dir.join("pop.gpg");
+    std::fs::write(&keyring, "").unwrap();
+
+    for &(path, ref expected) in &[
+        (&dir, Signature::KeyringDir(dir.clone())),
+        (&keyring, Signature::KeyringPath(keyring.clone())),
+        (&dir.join("missing"), Signature::KeyringPath(dir.join("missing"))),
+    ] {
+        let line = format!("deb [signed-by={}] http://x/ubuntu disco main", path.display());
+        let entry = line.parse::<SourceEntry>().unwrap();
+        assert_eq!(entry.signature().as_ref(), Some(expected));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn signed_by_multiple_keyrings() {
+    let entry = "deb [signed-by=/usr/share/keyrings/a.gpg,/etc/apt/keyrings/b.gpg] \
+                 http://x/ubuntu disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+
+    let keyrings =
+        vec![PathBuf::from("/usr/share/keyrings/a.gpg"), PathBuf::from("/etc/apt/keyrings/b.gpg")];
+    assert_eq!(entry.signed_by(), Some(keyrings[0].as_path()));
+    assert_eq!(entry.signed_by_all(), keyrings);
+
+    let signature = entry.signature().unwrap();
+    assert_eq!(signature, Signature::Keyrings(keyrings.clone()));
+    assert_eq!(signature.path(), keyrings[0].as_path());
+    assert_eq!(signature.to_string(), "/usr/share/keyrings/a.gpg,/etc/apt/keyrings/b.gpg");
+
+    let lists = SourcesLists::from_lists(vec![entry.to_string().parse().unwrap()]);
+    assert_eq!(lists.referenced_keyrings(), keyrings.into_iter().collect());
+
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.signed_by(), None);
+    assert!(entry.signed_by_all().is_empty());
+}
+
+#[test]
+fn set_signed_by() {
+    let mut entry = "deb [arch=amd64 signed-by=/a.gpg,/b.gpg lang=en signed-by=/c.gpg] \
+                     http://x/ubuntu disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+
+    entry.set_signed_by("/usr/share/keyrings/x.gpg");
+    assert_eq!(
+        entry.options.as_deref(),
+        Some("arch=amd64 signed-by=/usr/share/keyrings/x.gpg lang=en")
+    );
+    assert_eq!(entry.signed_by_all(), vec![PathBuf::from("/usr/share/keyrings/x.gpg")]);
+
+    let mut entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    entry.set_signed_by("/etc/apt/keyrings/y.gpg");
+    assert_eq!(
+        entry.to_string(),
+        "deb [signed-by=/etc/apt/keyrings/y.gpg] http://x/ubuntu disco main"
+    );
+}
+
+#[test]
+fn architectures() {
+    let mut entry =
+        "deb [arch=amd64,arm64 lang=en] http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.architectures(), vec!["amd64", "arm64"]);
+
+    assert!(entry.add_architecture("i386"));
+    assert!(!entry.add_architecture("amd64"));
+    assert_eq!(entry.options.as_deref(), Some("arch=amd64,arm64,i386 lang=en"));
+
+    assert!(entry.remove_architecture("arm64"));
+    assert!(!entry.remove_architecture("armhf"));
+    assert_eq!(entry.architectures(), vec!["amd64", "i386"]);
+
+    entry.remove_architecture("amd64");
+    entry.remove_architecture("i386");
+    assert!(entry.architectures().is_empty());
+    assert_eq!(entry.to_string(), "deb [lang=en] http://x/ubuntu disco main");
+
+    let mut entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    entry.add_architecture("amd64");
+    assert_eq!(entry.to_string(), "deb [arch=amd64] http://x/ubuntu disco main");
+    entry.remove_architecture("amd64");
+    assert_eq!(entry.options, None);
+}
+
+#[test]
+fn inline_comments() {
+    let line = "deb http://x/ubuntu focal main # mirror for CI";
+    let entry = line.parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.components, vec!["main".to_owned()]);
+    assert_eq!(entry.comment.as_deref(), Some("mirror for CI"));
+    assert_eq!(entry.to_string(), line);
+
+    let line = "# deb-src [arch=amd64] http://x/ubuntu focal main universe #no space";
+    match line.parse::<SourceLine>().unwrap() {
+        SourceLine::Entry(entry) => {
+            assert!(!entry.enabled);
+            assert_eq!(entry.components, vec!["main".to_owned(), "universe".to_owned()]);
+            assert_eq!(entry.comment.as_deref(), Some("no space"));
+            assert_eq!(entry.comment_spacing.as_deref(), Some(""));
+            assert_eq!(entry.to_string(), line);
+        }
+        other => panic!("expected an entry, found {:?}", other),
+    }
+
+    let entry =
+        "deb [signed-by=\"/a #b.gpg\"] http://x/ubuntu focal main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.comment, None);
+    assert_eq!(entry.signed_by(), Some(Path::new("/a #b.gpg")));
+
+    // A comment which is set directly is written after a `#`, and parsed back the same.
+    let mut entry = "deb http://x/ubuntu focal main #\tnote".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.to_string(), "deb http://x/ubuntu focal main #\tnote");
+    entry.comment = Some("changed".into());
+    entry.comment_spacing = None;
+    assert_eq!(entry.to_string(), "deb http://x/ubuntu focal main # changed");
+    assert_eq!(entry.to_string().parse::<SourceEntry>().unwrap(), entry);
+
+    // A `#` never begins a component, so this entry has no components.
+    assert!("deb http://x/ubuntu focal # main".parse::<SourceEntry>().is_err());
+}
+
+#[test]
+fn cdrom_entries() {
+    let line = "deb cdrom:[Pop_OS 18.04 _Bionic Beaver_]/ bionic main";
+    let entry = line.parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.url, "cdrom:[Pop_OS 18.04 _Bionic Beaver_]/");
+    assert_eq!(entry.suite, "bionic");
+    assert_eq!(entry.components, vec!["main".to_owned()]);
+    assert_eq!(entry.to_string(), line);
+
+    let lists = sources_lists();
+    match lists[0].lines[1] {
+        SourceLine::Entry(ref entry) => {
+            assert!(!entry.enabled);
+            assert!(entry.url.ends_with("_Bionic Beaver_ - Release amd64 (20180916)]/"));
+            assert_eq!(entry.to_string(), SOURCE_LIST.lines().nth(1).unwrap());
+        }
+        ref other => panic!("expected a cdrom entry, found {:?}", other),
+    }
+
+    assert!("deb cdrom:[Unterminated label bionic main".parse::<SourceEntry>().is_err());
+
+    // The label is kept verbatim, including runs of whitespace.
+    let line = "deb cdrom:[Pop_OS  18.04\tBeaver]/ bionic main";
+    let entry = line.parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.url, "cdrom:[Pop_OS  18.04\tBeaver]/");
+    assert_eq!(entry.suite, "bionic");
+    assert_eq!(entry.to_string(), line);
+}
+
+#[test]
+fn suite_membership() {
+    let entry = SourceEntry::from_str("deb http://x/ubuntu disco-updates main").unwrap();
+
+    assert!(entry.matches_release("disco"));
+    assert!(!entry.matches_release("disco-updates"));
+    assert!(!entry.matches_release("dis"));
+    assert!(entry.matches_any_suite(&["disco", "disco-updates"]));
+    assert!(!entry.matches_any_suite(&["disco", "disco-security"]));
+}
+
+#[test]
+fn dist_upgrade_preview() {
+    let lists = sources_lists();
+    let preview = lists.dist_upgrade_preview(&HashSet::new(), "disco", "eoan");
+
+    assert_eq!(preview.len(), 2);
+    assert_eq!(preview[0].1.len(), 12);
+    assert_eq!(preview[1].1.len(), 2);
+
+    let (ref before, ref after) = preview[1].1[0];
+    assert_eq!(before.suite, "disco");
+    assert_eq!(after, &before.with_suite("eoan"));
+
+    // The lists themselves are left untouched.
+    assert!(lists.entries().all(|entry| !entry.suite.starts_with("eoan")));
+    assert!(lists.modified.is_empty());
+
+    // Retained entries are left out, just as the upgrade itself leaves them alone.
+    let dir = test_dir("dist-upgrade-preview");
+    std::fs::write(dir.join("sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(dir.join("pop.list"), POP_PPA).unwrap();
+
+    let paths = [dir.join("sources.list"), dir.join("pop.list")];
+    let mut lists = SourcesLists::new_from_paths(paths.iter()).unwrap();
+    let mut retain = HashSet::new();
+    retain.insert("http://ppa.launchpad.net/system76/pop/ubuntu".into());
+
+    let preview = lists.dist_upgrade_preview(&retain, "disco", "eoan");
+    assert_eq!(preview.len(), 1);
+
+    let before = lists.clone();
+    lists.dist_upgrade(&retain, "disco", "eoan").unwrap();
+
+    let changed: Vec<(SourceEntry, SourceEntry)> = before
+        .entries()
+        .zip(lists.entries())
+        .filter(|(before, after)| before != after)
+        .map(|(before, after)| (before.clone(), after.clone()))
+        .collect();
+    assert_eq!(preview[0].1, changed);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dist_upgrade_diff() {
+    let lists = sources_lists();
+    let diff = lists.dist_upgrade_diff(&HashSet::new(), "disco", "eoan");
+
+    assert_eq!(diff.len(), 14);
+    assert_eq!(
+        diff[12],
+        (
+            lists[1].path.clone(),
+            "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main".to_owned(),
+            "deb http://ppa.launchpad.net/system76/pop/ubuntu eoan main".to_owned(),
+        )
+    );
+
+    assert!(diff.iter().all(|(_, before, after)| before.replace("disco", "eoan") == *after));
+    assert!(lists.modified.is_empty());
+
+    let mut retain = HashSet::new();
+    retain.insert("http://ppa.launchpad.net/system76/pop/ubuntu".into());
+    let diff = lists.dist_upgrade_diff(&retain, "disco", "eoan");
+    assert_eq!(diff.len(), 12);
+    assert!(diff.iter().all(|(path, _, _)| *path == lists[0].path));
+}
+
+#[test]
+fn bad_line() {
+    match "deb http://x/ubuntu disco main\ndeb http://x/ubuntu\n".parse::<SourcesList>() {
+        Err(SourcesListError::BadLine { line, text, .. }) => {
+            assert_eq!(line, 1);
+            assert_eq!(text, "deb http://x/ubuntu");
+        }
+        other => panic!("expected bad line error, found {:?}", other),
+    }
+}
+
+#[test]
+fn include_directive() {
+    const LIST: &str = "# include: fragment.list\ndeb http://x/ubuntu disco main\n";
+
+    let list = LIST.parse::<SourcesList>().unwrap();
+    assert_eq!(list.lines[0], SourceLine::Comment("# include: fragment.list".into()));
+
+    let options = ParseOptions { includes: true, ..ParseOptions::default() };
+    let list = SourcesList::parse_with(LIST, &options).unwrap();
+    assert_eq!(list.lines[0], SourceLine::Include(PathBuf::from("fragment.list")));
+    assert_eq!(list.to_string(), LIST);
+}
+
+#[test]
+fn resolve_includes() {
+    let root = test_dir("resolve-includes");
+    std::fs::write(root.join("main.list"), "# include: a.list\n").unwrap();
+    std::fs::write(root.join("a.list"), "# include: b.list\ndeb http://a/ubuntu disco main\n")
+        .unwrap();
+    std::fs::write(root.join("b.list"), "deb http://b/ubuntu disco main\n").unwrap();
+
+    let mut lists = SourcesLists::new_from_paths([root.join("main.list")].iter()).unwrap();
+    let provenance = lists.resolve_includes(&root).unwrap();
+    assert_eq!(
+        provenance,
+        vec![
+            (root.join("a.list"), root.join("main.list")),
+            (root.join("b.list"), root.join("a.list")),
+        ]
+    );
+    assert_eq!(lists.entries().count(), 2);
+
+    std::fs::write(root.join("b.list"), "# include: a.list\n").unwrap();
+    let mut lists = SourcesLists::new_from_paths([root.join("main.list")].iter()).unwrap();
+    match lists.resolve_includes(&root) {
+        Err(SourceError::IncludeCycle { path }) => assert_eq!(path, root.join("a.list")),
+        other => panic!("expected include cycle, found {:?}", other),
+    }
+
+    // The set is left as it was when an include fails.
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].lines, vec![SourceLine::Comment("# include: a.list".into())]);
+
+    std::fs::write(root.join("b.list"), "# include: missing.list\n").unwrap();
+    assert!(lists.resolve_includes(&root).is_err());
+    assert_eq!(lists.len(), 1);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn metadata_urls() {
+    let list = "deb [arch=amd64,i386] http://x/ubuntu disco main\n\
+                deb-src http://x/ubuntu disco main\n\
+                deb http://x/ubuntu disco main\n\
+                deb http://y/flat ./\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    assert_eq!(
+        lists.metadata_urls(),
+        vec![
+            "http://x/ubuntu/dists/disco/InRelease",
+            "http://x/ubuntu/dists/disco/main/binary-amd64/Packages",
+            "http://x/ubuntu/dists/disco/main/binary-i386/Packages",
+            "http://x/ubuntu/dists/disco/main/source/Sources",
+            "http://y/flat/InRelease",
+            "http://y/flat/Packages",
+        ]
+    );
+}
+
+#[test]
+fn ensure_twin() {
+    let list = "deb http://x/ubuntu disco main\n\
+                deb-src http://x/ubuntu disco-updates main\n\
+                deb http://x/ubuntu disco-updates main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    assert!(!lists.ensure_twin("http://x/ubuntu", "disco-updates"));
+    assert!(lists.modified.is_empty());
+
+    assert!(lists.ensure_twin("http://x/ubuntu", "disco"));
+    assert!(!lists.ensure_twin("http://x/ubuntu", "disco"));
+    assert_eq!(
+        lists[0].lines[1],
+        SourceLine::Entry("deb-src http://x/ubuntu disco main".parse().unwrap())
+    );
+    assert_eq!(lists.modified, vec![0]);
+}
+
+#[test]
+fn security_relaxations() {
+    const LIST: &str = "deb [allow-insecure=yes allow-weak=no] http://x/ubuntu disco main\n\
+                        deb [allow-weak=maybe] http://y/ubuntu disco main\n";
+
+    let list = LIST.parse::<SourcesList>().unwrap();
+    assert_eq!(list.to_string(), LIST);
+
+    let lists = SourcesLists::from_lists(vec![list]);
+    assert_eq!(lists.entries().next().unwrap().security_relaxations(), vec!["allow-insecure"]);
+    assert_eq!(
+        lists.validate(),
+        vec![
+            SourceWarning::SecurityRelaxed {
+                url: "http://x/ubuntu".into(),
+                suite: "disco".into(),
+                options: vec!["allow-insecure".into()],
+            },
+            SourceWarning::MalformedOption {
+                url: "http://y/ubuntu".into(),
+                suite: "disco".into(),
+                option: "allow-weak=maybe".into(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn find_entry() {
+    let mut lists = sources_lists();
+    lists[1].path = PathBuf::from("/etc/apt/sources.list.d/pop.list");
+
+    let (path, pos, entry) =
+        lists.find_entry("http://ppa.launchpad.net/system76/pop/ubuntu", "disco").unwrap();
+    assert_eq!(path, Path::new("/etc/apt/sources.list.d/pop.list"));
+    assert_eq!(pos, 1);
+    assert!(!entry.source);
+
+    assert!(lists.find_entry("http://ppa.launchpad.net/system76/pop/ubuntu", "eoan").is_none());
+}
+
+#[test]
+fn write_creates_parents() {
+    let root = test_dir("write-creates-parents");
+    let path = root.join("etc/apt/sources.list.d/new.list");
+
+    let mut lists = SourcesLists::from_lists(Vec::new());
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    lists.insert_entry(&path, entry).unwrap();
+    assert_eq!(lists.write_sync().unwrap(), vec![path.clone()]);
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "deb http://x/ubuntu disco main\n");
+    assert!(lists.write_sync().unwrap().is_empty());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn write_sync_written_paths() {
+    let root = test_dir("write-sync-written-paths");
+    let (a, b, c) = (root.join("a.list"), root.join("b.list"), root.join("c.list"));
+    std::fs::create_dir(&c).unwrap();
+
+    let mut lists = SourcesLists::from_lists(Vec::new());
+    let entry = |url: &str| format!("deb {} disco main", url).parse::<SourceEntry>().unwrap();
+    lists.insert_entry(&b, entry("http://b/ubuntu")).unwrap();
+    lists.insert_entry(&a, entry("http://a/ubuntu")).unwrap();
+    assert_eq!(lists.write_sync().unwrap(), vec![b.clone(), a.clone()]);
+
+    // The list which cannot be written, and those after it, are written by the next attempt.
+    lists.insert_entry(&c, entry("http://c/ubuntu")).unwrap();
+    lists.insert_entry(&a, entry("http://d/ubuntu")).unwrap();
+    assert!(lists.write_sync().is_err());
+    std::fs::remove_dir(&c).unwrap();
+    assert_eq!(lists.write_sync().unwrap(), vec![c, a]);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn write_sync_atomic() {
+    let root = test_dir("write-sync-atomic");
+    let path = root.join("pop.list");
+    std::fs::write(&path, POP_PPA).unwrap();
+
+    let mut list = SourcesList::new(&path).unwrap();
+    list.lines.push(SourceLine::Comment("# appended".into()));
+    list.write_sync().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), list.to_string());
+    assert!(!root.join("pop.list.tmp").exists());
+
+    // A list which cannot replace its path leaves the path untouched, and no temporary file.
+    let blocked = root.join("blocked.list");
+    std::fs::create_dir(&blocked).unwrap();
+    list.path = blocked.clone();
+    assert!(list.write_sync().is_err());
+    assert!(blocked.is_dir());
+    assert!(!root.join("blocked.list.tmp").exists());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+#[cfg(unix)]
+fn write_sync_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = test_dir("write-sync-permissions");
+    let path = root.join("pop.list");
+    std::fs::write(&path, POP_PPA).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let mut list = SourcesList::new(&path).unwrap();
+    list.write_sync().unwrap();
+    assert_eq!(mode(&path), 0o640);
+
+    let created = root.join("new.list");
+    let mut lists = SourcesLists::from_lists(Vec::new());
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    lists.insert_entry(&created, entry).unwrap();
+    lists.write_sync().unwrap();
+    assert_eq!(mode(&created), 0o644);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+#[cfg(unix)]
+fn transform_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = test_dir("transform-file-permissions");
+    let path = root.join("pop.list");
+    std::fs::write(&path, POP_PPA).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+    SourcesList::transform_file(&path, Some).expect("failed to transform file");
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn changes_from() {
+    let old = "deb http://x/ubuntu disco main restricted".parse::<SourceEntry>().unwrap();
+    let new = "# deb [arch=amd64] http://x/ubuntu eoan universe main";
+    let new = match new.parse::<SourceLine>().unwrap() {
+        SourceLine::Entry(entry) => entry,
+        other => panic!("expected entry, found {:?}", other),
+    };
+
+    assert_eq!(
+        new.changes_from(&old),
+        vec![
+            FieldChange::Enabled(false),
+            FieldChange::Options { old: None, new: Some("arch=amd64".into()) },
+            FieldChange::Suite { old: "disco".into(), new: "eoan".into() },
+            FieldChange::ComponentsAdded(vec!["universe".into()]),
+            FieldChange::ComponentsRemoved(vec!["restricted".into()]),
+        ]
+    );
+    assert!(old.changes_from(&old).is_empty());
+}
+
+#[test]
+fn option_tokens() {
+    let entry = "deb [ arch=amd64,i386  trusted=yes ] http://x/ubuntu disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+    assert_eq!(entry.option_tokens(), vec!["arch=amd64,i386", "trusted=yes"]);
+
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    assert!(entry.option_tokens().is_empty());
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn enable_if_valid_unreachable() {
+    let list = "# deb http://127.0.0.1:1/ubuntu disco main\n".parse::<SourcesList>().unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    match lists.enable_if_valid("http://127.0.0.1:1/ubuntu", "disco") {
+        Err(SourceError::ReleaseUnreachable { url, .. }) => {
+            assert_eq!(url, "http://127.0.0.1:1/ubuntu/dists/disco/InRelease")
+        }
+        other => panic!("expected unreachable release, found {:?}", other),
+    }
+
+    assert!(lists.entries().all(|entry| !entry.enabled));
+    assert!(lists.modified.is_empty());
+}
+
+#[test]
+fn duplicate_lines() {
+    const LIST: &str = "deb http://x/ubuntu disco main\n\
+                        \n\
+                        # comment\n\
+                        \n\
+                        deb http://x/ubuntu disco main\n\
+                        # comment\n";
+
+    let list = LIST.parse::<SourcesList>().unwrap();
+    assert_eq!(list.lines.len(), 3);
+    assert!(list.duplicate_lines().is_empty());
+
+    let options = ParseOptions { dedup: false, ..ParseOptions::default() };
+    let list = SourcesList::parse_with(LIST, &options).unwrap();
+    assert_eq!(list.duplicate_lines(), vec![4, 5]);
+    assert_eq!(list.to_string(), LIST);
+}
+
+#[test]
+fn origin_label() {
+    let entry = "deb http://us.archive.ubuntu.com/ubuntu/ disco-updates main"
+        .parse::<SourceEntry>()
+        .unwrap();
+    assert_eq!(entry.origin_label(), "us.archive.ubuntu.com disco-updates");
+
+    let entry = "deb file:/var/local/repo/ disco main".parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.origin_label(), "file:/var/local/repo disco");
+}
+
+#[test]
+fn insert_entry_merge() {
+    let mut lists = SourcesLists::from_lists(vec![list_at("/etc/apt/sources.list", SOURCE_LIST)]);
+
+    let entry = "deb [arch=amd64] http://apt.pop-os.org/proprietary disco universe main"
+        .parse::<SourceEntry>()
+        .unwrap();
+    lists.insert_entry_merge("/etc/apt/sources.list", entry).unwrap();
+
+    let (_, _, merged) = lists.find_entry("http://apt.pop-os.org/proprietary", "disco").unwrap();
+    assert_eq!(
+        merged.to_string(),
+        "deb [arch=amd64] http://apt.pop-os.org/proprietary disco main universe"
+    );
+    assert_eq!(lists.modified, vec![0]);
+
+    let entry = "deb [arch=i386 trusted=yes] http://apt.pop-os.org/proprietary disco main"
+        .parse::<SourceEntry>()
+        .unwrap();
+    lists.insert_entry_merge("/etc/apt/sources.list", entry).unwrap();
+
+    let (_, _, merged) = lists.find_entry("http://apt.pop-os.org/proprietary", "disco").unwrap();
+    assert_eq!(merged.options.as_deref(), Some("arch=i386 trusted=yes"));
+    assert_eq!(merged.components, vec!["main", "universe"]);
+
+    // Source entries are only merged into source entries.
+    let entry = "deb-src http://apt.pop-os.org/proprietary disco main restricted"
+        .parse::<SourceEntry>()
+        .unwrap();
+    lists.insert_entry_merge("/etc/apt/sources.list", entry).unwrap();
+
+    let (_, _, merged) = lists.find_entry("http://apt.pop-os.org/proprietary", "disco").unwrap();
+    assert_eq!(merged.components, vec!["main", "universe"]);
+    assert_eq!(
+        lists[0].lines.last(),
+        Some(&SourceLine::Entry(
+            "deb-src http://apt.pop-os.org/proprietary disco main restricted".parse().unwrap()
+        ))
+    );
+}
+
+#[test]
+fn referenced_keyrings() {
+    let list = "deb [signed-by=/usr/share/keyrings/a.gpg] http://a/ubuntu disco main\n\
+                deb [signed-by=/usr/share/keyrings/a.gpg] http://a/ubuntu disco-updates main\n\
+                deb [signed-by=/etc/apt/keyrings/b.gpg,0123456789ABCDEF] http://b/ disco main\n\
+                # deb [signed-by=/etc/apt/keyrings/c.gpg] http://c/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    let keyrings = lists.referenced_keyrings();
+    assert_eq!(
+        keyrings.into_iter().collect::<Vec<_>>(),
+        vec![PathBuf::from("/etc/apt/keyrings/b.gpg"), PathBuf::from("/usr/share/keyrings/a.gpg")]
+    );
+}
+
+#[test]
+fn templated_entries() {
+    const LIST: &str = "deb ${MIRROR} ${DISTRO}\n\
+                        deb-src [arch=${ARCH}] http://x/ubuntu disco main\n\
+                        deb http://x/ubuntu disco universe\n";
+
+    assert!(LIST.parse::<SourcesList>().is_err());
+
+    let options = ParseOptions { templates: true, ..ParseOptions::default() };
+    let list = SourcesList::parse_with(LIST, &options).unwrap();
+    assert_eq!(list.to_string(), LIST);
+
+    let lists = SourcesLists::from_lists(vec![list]);
+    let templated = lists.entries().map(SourceEntry::is_templated).collect::<Vec<_>>();
+    assert_eq!(templated, vec![true, true, false]);
+    assert!(lists.validate().is_empty());
+}
+
+#[test]
+fn rename_release_on_disk() {
+    let root = test_dir("rename-release-on-disk");
+    std::fs::create_dir_all(root.join("etc/apt/sources.list.d")).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list.d/pop.list"), POP_PPA).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list.d/other.list"), "deb http://x/ bionic main\n")
+        .unwrap();
+
+    let mut lists = SourcesLists::scan_filtered(&root, |_| true).unwrap();
+    lists.rename_release_on_disk("disco", "eoan").unwrap();
+
+    let pop = std::fs::read_to_string(root.join("etc/apt/sources.list.d/pop.list")).unwrap();
+    assert_eq!(pop, POP_PPA.replace("disco", "eoan"));
+    let sources = std::fs::read_to_string(root.join("etc/apt/sources.list")).unwrap();
+    assert!(sources.contains("deb http://us.archive.ubuntu.com/ubuntu/ eoan-updates "));
+    assert!(root.join("etc/apt/sources.list.d/pop.list.save").exists());
+    assert!(!root.join("etc/apt/sources.list.d/other.list.save").exists());
+
+    // A list which cannot be written causes the others to be restored.
+    let mut lists = SourcesLists::scan_filtered(&root, |_| true).unwrap();
+    lists.push(list_at(root.join("etc/apt/sources.list.d"), &POP_PPA.replace("disco", "eoan")));
+    assert!(lists.rename_release_on_disk("eoan", "focal").is_err());
+
+    let pop = std::fs::read_to_string(root.join("etc/apt/sources.list.d/pop.list")).unwrap();
+    assert_eq!(pop, POP_PPA.replace("disco", "eoan"));
+    assert!(lists.entries().all(|entry| !entry.suite.starts_with("focal")));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn unsigned_entries() {
+    let list = "deb [signed-by=/usr/share/keyrings/a.gpg] http://a/ubuntu disco main\n\
+                deb [trusted=yes] http://b/ubuntu disco main\n\
+                deb [trusted=no] http://c/ubuntu disco main\n\
+                deb http://d/ubuntu disco main\n\
+                # deb http://e/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    let unsigned = lists.unsigned_entries().map(|(_, entry)| entry.url.as_str());
+    assert_eq!(unsigned.collect::<Vec<_>>(), vec!["http://c/ubuntu", "http://d/ubuntu"]);
+}
+
+#[test]
+fn remove_entry_policy() {
+    let mut lists = sources_lists();
+    let repo = "http://ppa.launchpad.net/system76/pop/ubuntu";
+
+    lists.remove_entry(repo, RemovePolicy::Disable);
+    assert_eq!(lists[1].lines.len(), 3);
+    assert_eq!(lists[1].to_string(), POP_PPA_DISABLED);
+    assert_eq!(lists.modified, vec![1]);
+
+    lists.remove_entry(repo, RemovePolicy::Delete);
+    assert_eq!(lists[1].lines.len(), 2);
+    assert!(lists.find_entry(repo, "disco").unwrap().2.source);
+
+    let mut lists = sources_lists_pop_disabled();
+    lists.remove_entry(repo, RemovePolicy::Disable);
+    assert!(lists.modified.is_empty());
+}
+
+#[test]
+fn entry_at() {
+    let lists = sources_lists();
+    let count = lists.entries().count();
+
+    let (id, pos) = lists.entry_at(count - 1).unwrap();
+    assert_eq!((id, pos), (1, 2));
+    match lists[id][pos] {
+        SourceLine::Entry(ref entry) => assert_eq!(Some(entry), lists.entries().last()),
+        ref other => panic!("expected entry, found {:?}", other),
+    }
+
+    assert_eq!(lists.entry_at(count), None);
+}
+
+#[test]
+fn quoted_options() {
+    const LINE: &str =
+        r#"deb [arch=amd64 signed-by="/etc/apt/keyrings/my key.gpg"] http://x/ubuntu disco main"#;
+
+    let entry = LINE.parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.option("signed-by"), Some("/etc/apt/keyrings/my key.gpg"));
+    assert_eq!(entry.option("arch"), Some("amd64"));
+    assert_eq!(entry.option_tokens()[1], r#"signed-by="/etc/apt/keyrings/my key.gpg""#);
+    assert_eq!(entry.url, "http://x/ubuntu");
+    assert_eq!(entry.to_string(), LINE);
+}
+
+#[test]
+fn dedup_comments() {
+    const LIST: &str = "# Generated by a tool\n\
+                        # Generated by a tool\n\
+                        # Generated by a tool\n\
+                        # Do not edit\n\
+                        deb http://x/ubuntu disco main\n\
+                        # Generated by a tool\n";
+
+    let options = ParseOptions { dedup: false, ..ParseOptions::default() };
+    let list = SourcesList::parse_with(LIST, &options).unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    assert_eq!(lists.dedup_comments(), 1);
+    assert_eq!(
+        lists[0].to_string(),
+        "# Generated by a tool\n\
+         # Do not edit\n\
+         deb http://x/ubuntu disco main\n\
+         # Generated by a tool\n"
+    );
+    assert_eq!(lists.modified, vec![0]);
+    assert_eq!(lists.dedup_comments(), 0);
+}
+
+#[test]
+fn entries_fetch_order() {
+    let list = "deb http://a/ubuntu disco-backports main\n\
+                deb http://a/ubuntu disco main\n\
+                deb http://b/ubuntu disco-updates main\n\
+                deb http://b/ubuntu disco main\n\
+                deb http://a/ubuntu disco-updates main\n\
+                deb http://c/ubuntu disco-security main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    let order = lists
+        .entries_fetch_order()
+        .into_iter()
+        .map(|entry| [entry.url.as_str(), " ", &entry.suite].concat())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        order,
+        vec![
+            "http://c/ubuntu disco-security",
+            "http://a/ubuntu disco-updates",
+            "http://b/ubuntu disco-updates",
+            "http://a/ubuntu disco",
+            "http://b/ubuntu disco",
+            "http://a/ubuntu disco-backports",
+        ]
+    );
+}
+
+#[test]
+fn archive_kind() {
+    let kind = |line: &str| line.parse::<SourceEntry>().unwrap().archive_kind();
+
+    assert_eq!(kind("deb http://us.archive.ubuntu.com/ubuntu/ disco main"), ArchiveKind::Ubuntu);
+    assert_eq!(kind("deb http://security.ubuntu.com/ubuntu disco main"), ArchiveKind::Ubuntu);
+    assert_eq!(kind("deb http://deb.debian.org/debian buster main"), ArchiveKind::Debian);
+    assert_eq!(
+        kind("deb http://ppa.launchpad.net/system76/pop/ubuntu disco main"),
+        ArchiveKind::Launchpad
+    );
+    assert_eq!(kind("deb http://apt.pop-os.org/proprietary disco main"), ArchiveKind::ThirdParty);
+    assert_eq!(kind("deb http://notubuntu.com/ubuntu disco main"), ArchiveKind::ThirdParty);
+}
+
+#[test]
+fn from_ppa() {
+    let (entry, filename) = SourceEntry::from_ppa("ppa:system76/pop", "disco").unwrap();
+    assert_eq!(entry.to_string(), "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main");
+    assert_eq!(entry.ppa_shortcut().as_deref(), Some("ppa:system76/pop"));
+    assert_eq!(filename, "system76-ubuntu-pop-disco.list");
+
+    for invalid in
+        &["system76/pop", "ppa:system76", "ppa:system76/pop/extra", "ppa:/pop", "ppa:a b/c"]
+    {
+        assert!(SourceEntry::from_ppa(invalid, "disco").is_err(), "{} should be invalid", invalid);
+    }
+
+    assert!(SourceEntry::from_ppa("ppa:system76/pop", "").is_err());
+}
+
+#[test]
+fn to_deb822() {
+    let entry = "# deb [arch=amd64,i386 signed-by=/usr/share/keyrings/pop.gpg] \
+                 http://apt.pop-os.org/release disco main";
+    let entry = match entry.parse::<SourceLine>().unwrap() {
+        SourceLine::Entry(entry) => entry,
+        other => panic!("expected entry, found {:?}", other),
+    };
+
+    assert_eq!(
+        entry.to_deb822(),
+        "Types: deb\n\
+         URIs: http://apt.pop-os.org/release\n\
+         Suites: disco\n\
+         Components: main\n\
+         Architectures: amd64 i386\n\
+         Signed-By: /usr/share/keyrings/pop.gpg\n\
+         Enabled: no\n"
+    );
+}
+
+const POP_SOURCES: &str = "# Pop!_OS release repository
+Types: deb deb-src
+URIs: http://apt.pop-os.org/release
+Suites: disco
+Components: main
+signed-by:   /usr/share/keyrings/pop.gpg
+
+X-Repolib-Name: Pop PPA
+Types: deb
+URIs: http://ppa.launchpad.net/system76/pop/ubuntu
+Suites: disco disco-proposed
+Components: main
+Architectures: amd64 i386
+Enabled: no
+";
+
+#[test]
+fn parse_deb822() {
+    let list = SourcesList::parse_deb822(POP_SOURCES).unwrap();
+    assert!(list.deb822);
+    assert_eq!(list.to_string(), POP_SOURCES);
+
+    let entries: Vec<String> = list
+        .lines
+        .iter()
+        .filter_map(|line| match line {
+            SourceLine::Entry(entry) => Some(entry.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        entries,
+        vec![
+            "deb [signed-by=/usr/share/keyrings/pop.gpg] http://apt.pop-os.org/release disco main",
+            "deb-src [signed-by=/usr/share/keyrings/pop.gpg] http://apt.pop-os.org/release disco \
+             main",
+            "# deb [x-repolib-name=\"Pop PPA\" arch=amd64,i386] \
+             http://ppa.launchpad.net/system76/pop/ubuntu disco main",
+            "# deb [x-repolib-name=\"Pop PPA\" arch=amd64,i386] \
+             http://ppa.launchpad.net/system76/pop/ubuntu disco-proposed main",
+        ]
+    );
+
+    let stanza = "Types: deb\nURIs: http://x/ubuntu\nSuites: disco\nComponents: main\n\
+                  Signed-By:\n -----BEGIN PGP PUBLIC KEY BLOCK-----\n .\n -----END PGP PUBLIC KEY \
+                  BLOCK-----\n";
+    let entry = stanza.parse::<Deb822Entry>().unwrap();
+    assert_eq!(entry.to_string(), stanza);
+    assert_eq!(entry.field("components"), Some("main"));
+
+    match "Types: deb\nURIs: http://x/ubuntu\nSuites: disco\n".parse::<Deb822Entry>() {
+        Ok(entry) => match entry.to_entries() {
+            Err(SourceError::MissingField { field: "Components" }) => (),
+            other => panic!("expected missing components, found {:?}", other),
+        },
+        Err(why) => panic!("failed to parse stanza: {}", why),
+    }
+}
+
+#[test]
+fn deb822_edits() {
+    let dir = test_dir("deb822-edits");
+    let path = dir.join("etc/apt/sources.list.d/pop.sources");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, POP_SOURCES).unwrap();
+    std::fs::write(dir.join("etc/apt/sources.list"), "").unwrap();
+
+    let mut lists = SourcesLists::scan_filtered(&dir, |_| true).unwrap();
+    assert_eq!(lists.iter().filter(|list| list.deb822).count(), 1);
+
+    // Only the stanza of the changed entry is rewritten, and split as its entries now differ.
+    lists.set_pocket_enabled("disco", "proposed", true);
+    assert_eq!(lists.write_sync().unwrap(), vec![path.clone()]);
+    let expected = POP_SOURCES.replace(
+        "X-Repolib-Name: Pop PPA\n\
+         Types: deb\n\
+         URIs: http://ppa.launchpad.net/system76/pop/ubuntu\n\
+         Suites: disco disco-proposed\n\
+         Components: main\n\
+         Architectures: amd64 i386\n\
+         Enabled: no\n",
+        "X-Repolib-Name: Pop PPA\n\
+         Types: deb\n\
+         URIs: http://ppa.launchpad.net/system76/pop/ubuntu\n\
+         Suites: disco\n\
+         Components: main\n\
+         Architectures: amd64 i386\n\
+         Enabled: no\n\
+         \n\
+         X-Repolib-Name: Pop PPA\n\
+         Types: deb\n\
+         URIs: http://ppa.launchpad.net/system76/pop/ubuntu\n\
+         Suites: disco-proposed\n\
+         Components: main\n\
+         Architectures: amd64 i386\n",
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+    // New lists are written in the format of their extension.
+    let new = dir.join("etc/apt/sources.list.d/new.sources");
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    lists.insert_entry(&new, entry).unwrap();
+    lists.write_sync().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&new).unwrap(),
+        "Types: deb\nURIs: http://x/ubuntu\nSuites: disco\nComponents: main\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn from_lines() {
+    let entry = "deb http://x/ubuntu disco main".parse::<SourceEntry>().unwrap();
+    let list = SourcesList::from_lines("x.list", vec![SourceLine::Entry(entry.clone())]);
+    assert!(!list.deb822);
+    assert_eq!(list.to_string(), "deb http://x/ubuntu disco main\n");
+
+    let list = SourcesList::from_lines("x.sources", vec![SourceLine::Entry(entry)]);
+    assert!(list.deb822);
+    assert_eq!(
+        list.to_string(),
+        "Types: deb\nURIs: http://x/ubuntu\nSuites: disco\nComponents: main\n"
+    );
+}
+
+#[test]
+fn deb822_round_trip() {
+    let dir = test_dir("deb822-round-trip");
+    let path = dir.join("pop.sources");
+    std::fs::create_dir_all(&dir).unwrap();
+    let stanza = "Types: deb deb-src\n\
+                  # The release repository\n\
+                  URIs: http://apt.pop-os.org/release\n\
+                  Suites: disco disco-updates\n\
+                  Components: main\n";
+    std::fs::write(&path, stanza).unwrap();
+
+    let entries = |list: &SourcesList| -> Vec<String> {
+        list.lines
+            .iter()
+            .filter_map(|line| match line {
+                SourceLine::Entry(entry) => Some(entry.to_string()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // Editing the entries of the stanza keeps its grouping and comments.
+    let mut lists = SourcesLists::from_lists(vec![SourcesList::new(&path).unwrap()]);
+    lists.dist_replace("disco", "eoan");
+    lists.write_sync().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), stanza.replace("disco", "eoan"));
+
+    // A new entry, and the comment above it, are separated from the stanza before them.
+    let mut lists = SourcesLists::from_lists(vec![SourcesList::new(&path).unwrap()]);
+    let entry = "deb http://ppa.launchpad.net/system76/pop/ubuntu eoan main";
+    let path_str = path.to_str().unwrap();
+    lists.insert_entry_with_comment(path_str, "Pop PPA", entry.parse().unwrap()).unwrap();
+    lists.write_sync().unwrap();
+
+    let list = SourcesList::new(&path).unwrap();
+    assert_eq!(entries(&list).len(), 5);
+    assert_eq!(entries(&list)[4], entry);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        [
+            &stanza.replace("disco", "eoan"),
+            "\n#: Pop PPA\n",
+            "Types: deb\nURIs: http://ppa.launchpad.net/system76/pop/ubuntu\nSuites: eoan\n",
+            "Components: main\n",
+        ]
+        .concat()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn deb822_dist_upgrade() {
+    let dir = test_dir("deb822-dist-upgrade");
+    let parts = dir.join("etc/apt/sources.list.d");
+    std::fs::create_dir_all(&parts).unwrap();
+    std::fs::write(dir.join("etc/apt/sources.list"), "").unwrap();
+    std::fs::write(parts.join("pop.sources"), POP_SOURCES).unwrap();
+
+    let mut lists = SourcesLists::scan_from_root(&dir).unwrap();
+    lists.dist_upgrade(&std::collections::HashSet::new(), "disco", "eoan").unwrap();
+
+    let upgraded = std::fs::read_to_string(parts.join("pop.sources")).unwrap();
+    assert!(upgraded.lines().all(|line| !line.starts_with("deb")));
+    assert_eq!(std::fs::read_to_string(parts.join("pop.sources.save")).unwrap(), POP_SOURCES);
+
+    let list = SourcesList::new(parts.join("pop.sources")).unwrap();
+    let suites: Vec<&str> = list
+        .lines
+        .iter()
+        .filter_map(|line| match line {
+            SourceLine::Entry(entry) => Some(entry.suite.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(suites, vec!["eoan", "eoan", "eoan", "eoan-proposed"]);
+
+    // Lists in the deb822 format cannot be streamed by line.
+    let error = SourcesList::transform_file(parts.join("pop.sources"), Some).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Backups of deb822 lists are parsed in the same format.
+    let backups = SourcesLists::scan_backups(&dir).unwrap();
+    let backup = &backups.iter().find(|(path, _)| path.ends_with("pop.sources.save")).unwrap().1;
+    assert!(backup.deb822);
+    assert_eq!(backup.to_string(), POP_SOURCES);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_from_apt_config() {
+    let root = test_dir("scan-from-apt-config");
+    std::fs::create_dir_all(root.join("parts")).unwrap();
+    std::fs::write(root.join("main.list"), "deb http://a/ubuntu disco main\n").unwrap();
+    std::fs::write(root.join("parts/b.list"), "deb http://b/ubuntu disco main\n").unwrap();
+    std::fs::write(root.join("parts/ignored.txt"), "deb http://c/ubuntu disco main\n").unwrap();
+
+    let conf = AptConfig { sourcelist: root.join("main.list"), sourceparts: root.join("parts") };
+    let lists = SourcesLists::scan_from_apt_config(&conf).unwrap();
+
+    let urls = lists.entries().map(|entry| entry.url.as_str()).collect::<Vec<_>>();
+    assert_eq!(urls, vec!["http://a/ubuntu", "http://b/ubuntu"]);
+    assert_eq!(AptConfig::standard().sourceparts, Path::new("/etc/apt/sources.list.d"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn scan_from_root() {
+    let root = test_dir("scan-from-root");
+    let parts = root.join("etc/apt/sources.list.d");
+    std::fs::create_dir_all(&parts).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list"), SOURCE_LIST).unwrap();
+    std::fs::write(parts.join("pop.list"), POP_PPA).unwrap();
+
+    let lists = SourcesLists::scan_from_root(&root).unwrap();
+    let paths = lists.iter().map(|list| list.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths, vec![root.join("etc/apt/sources.list"), parts.join("pop.list")]);
+    assert_eq!(lists.entries().count(), sources_lists().entries().count());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn options_builder() {
+    let options = OptionsBuilder::new()
+        .option("arch", "i386")
+        .option("signed-by", "/etc/apt/keyrings/my key.gpg")
+        .option("arch", "amd64")
+        .option("trusted", "no")
+        .build()
+        .unwrap();
+    assert_eq!(
+        options.as_deref(),
+        Some(r#"arch=amd64 signed-by="/etc/apt/keyrings/my key.gpg" trusted=no"#)
+    );
+
+    let conflict =
+        OptionsBuilder::new().option("trusted", "yes").option("signed-by", "/a.gpg").build();
+    match conflict {
+        Err(SourceError::InvalidValue { field: "options", .. }) => (),
+        other => panic!("expected conflict, found {:?}", other),
+    }
+
+    assert_eq!(OptionsBuilder::new().build().unwrap(), None);
+}
+
+#[test]
+fn source_entry_builder() {
+    let entry = SourceEntryBuilder::new()
+        .url("http://ppa.launchpad.net/system76/pop/ubuntu")
+        .suite("disco")
+        .component("main")
+        .components(&["main", "universe"])
+        .source(true)
+        .arch("amd64")
+        .arch("i386")
+        .signed_by("/usr/share/keyrings/pop.gpg")
+        .build()
+        .unwrap();
+    assert_eq!(
+        entry.to_string(),
+        "deb-src [arch=amd64,i386 signed-by=/usr/share/keyrings/pop.gpg] \
+         http://ppa.launchpad.net/system76/pop/ubuntu disco main universe"
+    );
+
+    let flat = SourceEntryBuilder::new().url("file:///srv/repo").suite("./").build().unwrap();
+    assert_eq!(flat.to_string(), "deb file:///srv/repo ./");
+
+    let missing = |builder: SourceEntryBuilder| match builder.build() {
+        Err(SourceError::MissingField { field }) => field,
+        other => panic!("expected a missing field, found {:?}", other),
+    };
+
+    let url = "http://x/ubuntu";
+    assert_eq!(missing(SourceEntryBuilder::new().suite("disco").component("main")), "url");
+    assert_eq!(missing(SourceEntryBuilder::new().url(url).component("main")), "suite");
+    assert_eq!(missing(SourceEntryBuilder::new().url(url).suite("disco")), "components");
+}
+
+#[test]
+fn index_entries() {
+    let entry = "deb [arch=amd64,i386] http://archive.ubuntu.com/ubuntu/ disco main universe"
+        .parse::<SourceEntry>()
+        .unwrap();
+    assert_eq!(
+        entry.index_entries(),
+        vec![
+            "http://archive.ubuntu.com/ubuntu disco InRelease",
+            "http://archive.ubuntu.com/ubuntu disco/main amd64 Packages",
+            "http://archive.ubuntu.com/ubuntu disco/main i386 Packages",
+            "http://archive.ubuntu.com/ubuntu disco/universe amd64 Packages",
+            "http://archive.ubuntu.com/ubuntu disco/universe i386 Packages",
+        ]
+    );
+
+    let entry = "deb-src http://archive.ubuntu.com/ubuntu disco main";
+    let entry = entry.parse::<SourceEntry>().unwrap();
+    assert_eq!(entry.index_entries()[1], "http://archive.ubuntu.com/ubuntu disco/main Sources");
+}
+
+#[test]
+fn byte_order_mark() {
+    const LIST: &str = "\u{feff}deb http://x/ubuntu disco main\n# comment\n";
+
+    let mut list = LIST.parse::<SourcesList>().unwrap();
+    assert!(list.bom);
+    assert_eq!(list.lines[0], SourceLine::Entry("deb http://x/ubuntu disco main".parse().unwrap()));
+    assert_eq!(list.to_string(), LIST);
+    assert!(!list.normalize());
+
+    let root = test_dir("byte-order-mark");
+    let path = root.join("bom.list");
+    std::fs::write(&path, LIST).unwrap();
+    SourcesList::transform_file(&path, Some).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), LIST);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn add_component_for_suite() {
+    let mut lists = sources_lists();
+    let repo = "http://us.archive.ubuntu.com/ubuntu/";
+
+    assert!(lists.add_component_for_suite(repo, "disco", "partner"));
+    assert!(!lists.add_component_for_suite(repo, "disco", "partner"));
+
+    let with_partner = lists
+        .entries()
+        .filter(|entry| entry.components.iter().any(|c| c == "partner"))
+        .map(|entry| entry.suite.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(with_partner, vec!["disco", "disco"]);
+    assert_eq!(lists.modified, vec![0]);
+}
+
+#[test]
+fn iterate_across_files() {
+    let a = "deb http://a/ubuntu disco main\ndeb-src http://a/ubuntu disco main\n";
+    let b = "# comment\ndeb http://b/ubuntu disco main\n";
+    let lists = SourcesLists::from_lists(vec![list_at("a.list", a), list_at("b.list", b)]);
+
+    let mut seen = Vec::new();
+    for list in lists.iter() {
+        seen.push(list.path.display().to_string());
+        for line in &list.lines {
+            if let SourceLine::Entry(ref entry) = *line {
+                seen.push(entry.to_string());
+            }
+        }
+    }
+
+    assert_eq!(
+        seen,
+        vec![
+            "a.list",
+            "deb http://a/ubuntu disco main",
+            "deb-src http://a/ubuntu disco main",
+            "b.list",
+            "deb http://b/ubuntu disco main",
+        ]
+    );
+    assert_eq!(lists.entries().count(), 3);
+}
+
+#[test]
+fn annotations() {
+    let mut lists = sources_lists();
+    let repo = "http://ppa.launchpad.net/system76/pop/ubuntu";
+    let entry = lists.find_entry(repo, "disco").unwrap().2.clone();
+
+    lists.annotate(&entry, "added by user on 2019-04-18");
+    lists.repo_modify(repo, false);
+
+    let disabled = lists.find_entry(repo, "disco").unwrap().2.clone();
+    assert_eq!(lists.annotation(&disabled), Some("added by user on 2019-04-18"));
+    assert_eq!(lists[1].to_string(), POP_PPA_DISABLED);
+
+    assert!(lists.remove_annotation(&entry).is_some());
+    assert_eq!(lists.annotation(&entry), None);
+}
+
+#[test]
+fn missing_pockets() {
+    let list = "deb http://archive.ubuntu.com/ubuntu disco main\n\
+                deb http://archive.ubuntu.com/ubuntu disco-updates main\n\
+                # deb http://security.ubuntu.com/ubuntu disco-security main\n\
+                deb http://archive.ubuntu.com/ubuntu eoan main\n\
+                deb http://security.ubuntu.com/ubuntu eoan-security main\n\
+                deb http://archive.ubuntu.com/ubuntu eoan-updates main\n\
+                deb http://deb.debian.org/debian sid main\n\
+                deb http://apt.example.com/repo stable main\n\
+                deb file:/srv/repo ./\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    assert_eq!(lists.missing_pockets(), vec![("disco".to_owned(), vec!["security".to_owned()])]);
+    assert_eq!(
+        lists.missing_pockets_of(&["backports"]),
+        vec![
+            ("disco".to_owned(), vec!["backports".to_owned()]),
+            ("eoan".to_owned(), vec!["backports".to_owned()]),
+        ]
+    );
+}
+
+#[test]
+fn write_to_dir() {
+    let mut lists = sources_lists();
+    lists[0].path = PathBuf::from("/etc/apt/sources.list");
+    lists[1].path = PathBuf::from("/etc/apt/sources.list.d/pop.list");
+    lists.repo_modify("http://ppa.launchpad.net/system76/pop/ubuntu", false);
+
+    let dir = test_dir("write-to-dir");
+    lists.write_to_dir(&dir).unwrap();
+
+    assert_eq!(std::fs::read_to_string(dir.join("sources.list")).unwrap(), lists[0].to_string());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("sources.list.d/pop.list")).unwrap(),
+        POP_PPA_DISABLED
+    );
+    assert_eq!(lists.modified, vec![1]);
+
+    // Lists which would be written outside of the directory are rejected, writing nothing.
+    let escape = dir.join("escape");
+    for (a, b) in &[("a.list", "/etc/apt/b.list"), ("", "/etc/apt/b.list")] {
+        let mut lists = sources_lists();
+        lists[0].path = PathBuf::from(a);
+        lists[1].path = PathBuf::from(b);
+        let error = lists.write_to_dir(&escape).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!escape.exists());
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn parse_collecting() {
+    const LIST: &str = "deb http://x/ubuntu disco main\n\
+                        deb http://x/ubuntu\n\
+                        # comment\n\
+                        rpm http://x/fedora 30 main\n";
+
+    let (list, errors) = SourcesList::parse_collecting(LIST);
+    assert_eq!(list.lines.len(), 4);
+    assert_eq!(list.lines[1], SourceLine::Invalid("deb http://x/ubuntu".into()));
+    assert_eq!(list.to_string(), LIST);
+
+    let lines = errors
+        .iter()
+        .map(|error| match *error {
+            SourcesListError::BadLine { line, .. } => line,
+            ref other => panic!("expected bad line error, found {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![1, 3]);
+}
+
+#[test]
+fn invalid_lines() {
+    let (mut list, _) = SourcesList::parse_collecting("deb http://x/ubuntu\n# comment\nnope\n");
+    assert_eq!(list.invalid_lines(), vec![0, 2]);
+
+    match list.ensure_valid() {
+        Err(SourcesListError::BadLine { line: 0, text, .. }) => {
+            assert_eq!(text, "deb http://x/ubuntu")
+        }
+        other => panic!("expected bad line error, found {:?}", other),
+    }
+
+    list.lines.remove(2);
+    list.lines.remove(0);
+    assert!(list.ensure_valid().is_ok());
+}
+
+#[test]
+fn duplicate_options() {
+    let list = "deb [arch=amd64 trusted=yes arch=arm64] http://x/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    let entry = lists.entries().next().unwrap();
+    assert_eq!(entry.option("arch"), Some("arm64"));
+    assert_eq!(entry.duplicate_options(), vec!["arch"]);
+    assert_eq!(
+        lists.validate(),
+        vec![SourceWarning::DuplicateOption {
+            url: "http://x/ubuntu".into(),
+            suite: "disco".into(),
+            key: "arch".into(),
+        }]
+    );
+
+    assert_eq!(lists.normalize(), 1);
+    assert_eq!(lists[0].to_string(), "deb [trusted=yes arch=arm64] http://x/ubuntu disco main\n");
+    assert!(lists.validate().is_empty());
+}
+
+#[test]
+fn to_commands() {
+    let commands = sources_lists().to_commands();
+    assert_eq!(commands.len(), 12);
+    assert_eq!(
+        commands[0],
+        "add-apt-repository 'deb http://us.archive.ubuntu.com/ubuntu/ disco restricted \
+         multiverse universe main'"
+    );
+    assert_eq!(
+        commands[10],
+        "add-apt-repository 'deb http://apt.pop-os.org/proprietary disco main'"
+    );
+    assert_eq!(commands[11], "add-apt-repository -s ppa:system76/pop");
+
+    assert!(sources_lists_pop_disabled().to_commands().iter().all(|c| !c.contains("ppa:")));
+}
+
+#[test]
+fn current_release() {
+    let mut lists = sources_lists();
+    assert_eq!(lists.current_release(), Ok("disco".into()));
+
+    // The PPA lagging behind does not conflict.
+    lists[1].lines[1] =
+        "deb http://ppa.launchpad.net/system76/pop/ubuntu cosmic main".parse().unwrap();
+    assert_eq!(lists.current_release(), Ok("disco".into()));
+
+    lists[0].lines[3] =
+        "deb http://us.archive.ubuntu.com/ubuntu/ eoan-updates main".parse().unwrap();
+    assert_eq!(
+        lists.current_release(),
+        Err(ReleaseError::Conflict { releases: vec!["disco".into(), "eoan".into()] })
+    );
+
+    let empty = SourcesLists::from_lists(vec![POP_PPA.parse().unwrap()]);
+    assert_eq!(empty.current_release(), Err(ReleaseError::NoOfficialEntries));
+}
+
+#[test]
+fn preserve_tab_separators() {
+    let input = "deb\thttp://us.archive.ubuntu.com/ubuntu/\tdisco main\n\
+                 deb http://apt.pop-os.org/proprietary disco main\n";
+    let mut lists = SourcesLists::from_lists(vec![input.parse::<SourcesList>().unwrap()]);
+    assert_eq!(lists[0].to_string(), input);
+
+    // Only the edited line is re-rendered.
+    lists.repo_modify("http://apt.pop-os.org/proprietary", false);
+    assert_eq!(
+        lists[0].to_string(),
+        "deb\thttp://us.archive.ubuntu.com/ubuntu/\tdisco main\n\
+         # deb http://apt.pop-os.org/proprietary disco main\n"
+    );
+
+    assert_eq!(lists.normalize(), 1);
+    assert_eq!(
+        lists[0].to_string(),
+        "deb http://us.archive.ubuntu.com/ubuntu/ disco main\n\
+         # deb http://apt.pop-os.org/proprietary disco main\n"
+    );
+}
+
+#[test]
+fn source_counterparts() {
+    let entry = "deb [arch=amd64] http://apt.pop-os.org/proprietary disco main restricted"
+        .parse::<SourceEntry>()
+        .unwrap();
+
+    let source = entry.as_source();
+    assert_eq!(
+        source.to_string(),
+        "deb-src [arch=amd64] http://apt.pop-os.org/proprietary disco main restricted"
+    );
+    assert_eq!(source.as_binary(), entry);
+    assert_eq!(source.as_source(), source);
+}
+
+#[test]
+fn semantically_eq() {
+    let lists = sources_lists();
+    assert!(lists.semantically_eq(&sources_lists()));
+
+    // Moving every entry into one list, in a different order and form, changes nothing.
+    let mut entries: Vec<String> = lists.active_entries().map(|entry| entry.to_string()).collect();
+    entries.reverse();
+    entries[0] = "deb-src http://ppa.launchpad.net/system76/pop/ubuntu/ disco main".into();
+    entries.push("# a comment".into());
+    let merged = SourcesLists::from_lists(vec![entries.join("\n").parse().unwrap()]);
+    assert!(lists.semantically_eq(&merged));
+    assert!(merged.semantically_eq(&lists));
+
+    assert!(!lists.semantically_eq(&sources_lists_pop_disabled()));
+}
+
+#[test]
+fn place_entry() {
+    let conf = AptConfig::with_root("/chroot");
+    let parts = PathBuf::from("/chroot/etc/apt/sources.list.d");
+    let mut lists = sources_lists();
+
+    for &(line, ref expected) in &[
+        (
+            "deb http://ppa.launchpad.net/system76/pop/ubuntu eoan main",
+            parts.join("system76-ubuntu-pop-eoan.list"),
+        ),
+        (
+            "deb https://repo.example.com:8443/apt stable main",
+            parts.join("repo.example.com_8443.list"),
+        ),
+        (
+            "deb http://us.archive.ubuntu.com/ubuntu/ eoan main",
+            PathBuf::from("/chroot/etc/apt/sources.list"),
+        ),
+    ] {
+        let entry = line.parse::<SourceEntry>().unwrap();
+        let path = lists.place_entry_with(&conf, entry.clone()).unwrap();
+        assert_eq!(path, *expected);
+
+        let list = lists.iter().find(|list| list.path == path).unwrap();
+        assert!(list.lines.contains(&SourceLine::Entry(entry)));
+    }
+
+    assert_eq!(lists.len(), 5);
+}
+
+#[test]
+fn check_valid_until() {
+    let list = "deb [check-valid-until=no] http://snapshot.example.com/ubuntu disco main\n\
+                deb [check-valid-until=nope] http://x/ubuntu disco main\n\
+                deb http://y/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let lists = SourcesLists::from_lists(vec![list]);
+
+    let checks: Vec<Option<bool>> = lists.entries().map(SourceEntry::check_valid_until).collect();
+    assert_eq!(checks, vec![Some(false), None, None]);
+    assert!(lists[0].to_string().contains("[check-valid-until=nope]"));
+
+    assert_eq!(
+        lists.validate(),
+        vec![
+            SourceWarning::ValidUntilUnchecked {
+                url: "http://snapshot.example.com/ubuntu".into(),
+                suite: "disco".into(),
+            },
+            SourceWarning::MalformedOption {
+                url: "http://x/ubuntu".into(),
+                suite: "disco".into(),
+                option: "check-valid-until=nope".into(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn insert_entry_sorted() {
+    let list = "# Managed by a tool\n\
+                deb http://a.example.com/apt stable main\n\
+                deb http://c.example.com/apt stable main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+    let path = lists[0].path.clone();
+
+    for line in &[
+        "deb http://b.example.com/apt stable main",
+        "deb http://0.example.com/apt stable main",
+        "deb http://d.example.com/apt stable main",
+        "deb http://b.example.com/apt stable main",
+    ] {
+        lists.insert_entry_sorted(&path, line.parse().unwrap()).unwrap();
+    }
+
+    assert_eq!(
+        lists[0].to_string(),
+        "# Managed by a tool\n\
+         deb http://0.example.com/apt stable main\n\
+         deb http://a.example.com/apt stable main\n\
+         deb http://b.example.com/apt stable main\n\
+         deb http://c.example.com/apt stable main\n\
+         deb http://d.example.com/apt stable main\n"
+    );
+
+    // Comments above an entry stay with it.
+    let list = "# Managed by a tool\n\
+                deb http://a.example.com/apt stable main\n\
+                # Mirror of c\n\
+                deb http://c.example.com/apt stable main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+    let path = lists[0].path.clone();
+
+    let entry = "deb http://b.example.com/apt stable main".parse().unwrap();
+    lists.insert_entry_sorted(&path, entry).unwrap();
+    assert_eq!(
+        lists[0].to_string(),
+        "# Managed by a tool\n\
+         deb http://a.example.com/apt stable main\n\
+         deb http://b.example.com/apt stable main\n\
+         # Mirror of c\n\
+         deb http://c.example.com/apt stable main\n"
+    );
+}
+
+#[test]
+fn explode() {
+    let entry = "deb [arch=amd64] http://x/ubuntu disco main universe";
+    let split: Vec<String> = entry
+        .parse::<SourceEntry>()
+        .unwrap()
+        .split_components()
+        .iter()
+        .map(|entry| entry.to_string())
+        .collect();
+    assert_eq!(
+        split,
+        vec![
+            "deb [arch=amd64] http://x/ubuntu disco main",
+            "deb [arch=amd64] http://x/ubuntu disco universe"
+        ]
+    );
+
+    let flat = "deb http://x/repo ./".parse::<SourceEntry>().unwrap();
+    assert_eq!(flat.split_components(), vec![flat.clone()]);
+
+    let mut lists = SourcesLists::from_lists(vec![
+        "# comment\ndeb http://x/ubuntu disco main universe\n".parse().unwrap(),
+        POP_PPA.parse().unwrap(),
+    ]);
+    assert_eq!(lists.explode(), 1);
+    assert_eq!(lists.modified, vec![0]);
+    assert_eq!(
+        lists[0].to_string(),
+        "# comment\ndeb http://x/ubuntu disco main\ndeb http://x/ubuntu disco universe\n"
+    );
+}
+
+#[test]
+fn shadowed_entries() {
+    let mut main = "deb http://x/ubuntu disco main universe\n\
+                    deb http://x/ubuntu/ disco main\n\
+                    deb [arch=i386] http://x/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    main.path = PathBuf::from("/etc/apt/sources.list");
+
+    let mut copy = "deb http://x/ubuntu disco main universe\ndeb-src http://x/ubuntu disco main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    copy.path = PathBuf::from("/etc/apt/sources.list.d/copy.list");
+
+    let lists = SourcesLists::from_lists(vec![main, copy]);
+    let shadowed: Vec<(PathBuf, usize)> = lists
+        .validate()
+        .into_iter()
+        .filter_map(|warning| match warning {
+            SourceWarning::ShadowedEntry { path, index } => Some((path, index)),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        shadowed,
+        vec![
+            (PathBuf::from("/etc/apt/sources.list"), 1),
+            (PathBuf::from("/etc/apt/sources.list.d/copy.list"), 0),
+        ]
+    );
+}
+
+#[test]
+fn enable_source_for_release() {
+    let list = "deb http://x/ubuntu disco main\n\
+                # deb-src http://x/ubuntu disco main\n\
+                deb http://x/ubuntu disco-security main\n\
+                deb http://x/ubuntu disco-updates main\n\
+                deb-src http://x/ubuntu disco-updates main\n\
+                deb http://x/ubuntu eoan main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    assert_eq!(lists.enable_source_for_release("disco"), 2);
+    assert_eq!(lists.modified, vec![0]);
+    assert_eq!(
+        lists[0].to_string(),
+        "deb http://x/ubuntu disco main\n\
+         deb-src http://x/ubuntu disco main\n\
+         deb http://x/ubuntu disco-security main\n\
+         deb-src http://x/ubuntu disco-security main\n\
+         deb http://x/ubuntu disco-updates main\n\
+         deb-src http://x/ubuntu disco-updates main\n\
+         deb http://x/ubuntu eoan main\n"
+    );
+
+    assert_eq!(lists.enable_source_for_release("disco"), 0);
+}
+
+#[test]
+fn locked_entries() {
+    let input = "## deb http://x/ubuntu disco main\n\
+                 # deb http://x/ubuntu disco-proposed main\n\
+                 ## deb-src http://x/ubuntu disco-proposed main\n";
+
+    let unlocked = input.parse::<SourcesList>().unwrap();
+    assert_eq!(unlocked[0], SourceLine::Comment("## deb http://x/ubuntu disco main".into()));
+
+    let options = ParseOptions { locks: Some(LOCK_MARKER.into()), ..ParseOptions::default() };
+    let list = SourcesList::parse_with(input, &options).unwrap();
+    match list[0] {
+        SourceLine::Entry(ref entry) => assert!(entry.locked && !entry.enabled),
+        ref other => panic!("expected a locked entry, found {:?}", other),
+    }
+    assert_eq!(list.to_string(), input);
+
+    let mut lists = SourcesLists::from_lists(vec![list]);
+    assert!(lists.repo_modify("http://x/ubuntu", true));
+    assert_eq!(lists.set_pocket_enabled("disco", "proposed", true), 0);
+    assert_eq!(
+        lists[0].to_string(),
+        "## deb http://x/ubuntu disco main\n\
+         deb http://x/ubuntu disco-proposed main\n\
+         ## deb-src http://x/ubuntu disco-proposed main\n"
+    );
+
+    assert_eq!(lists.enable_source_for_release("disco"), 0);
+}
+
+#[test]
+fn custom_lock_marker() {
+    let input = "#! deb http://x/ubuntu disco main\n\
+                 # deb http://x/ubuntu disco main\n";
+
+    let options =
+        ParseOptions { locks: Some("#!".into()), dedup: false, ..ParseOptions::default() };
+    let list = SourcesList::parse_with(input, &options).unwrap();
+    assert_eq!(list.to_string(), input);
+
+    let (locked, unlocked) = match (&list[0], &list[1]) {
+        (SourceLine::Entry(locked), SourceLine::Entry(unlocked)) => (locked, unlocked),
+        other => panic!("expected two entries, found {:?}", other),
+    };
+
+    assert!(locked.locked && !unlocked.locked);
+    assert_ne!(locked, unlocked);
+    assert_ne!(locked.cmp(unlocked), std::cmp::Ordering::Equal);
+
+    // Edited entries keep the marker that the list was parsed with.
+    let mut list = list.clone();
+    if let SourceLine::Entry(ref mut entry) = list.lines[0] {
+        entry.suite = "eoan".into();
+    }
+    assert_eq!(
+        list.to_string(),
+        "#! deb http://x/ubuntu eoan main\n\
+         # deb http://x/ubuntu disco main\n"
+    );
+
+    let root = test_dir("custom-lock-marker");
+    std::fs::create_dir_all(root.join("etc/apt/sources.list.d")).unwrap();
+    std::fs::write(root.join("etc/apt/sources.list"), input).unwrap();
+
+    let lists = SourcesLists::scan_from_root_with(&root, &options).unwrap();
+    assert!(lists.entries().any(|entry| entry.locked));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn plan_and_apply() {
+    let mut list = "deb http://x/ubuntu disco main\n\
+                    # deb http://x/ubuntu disco-updates main\n\
+                    deb http://x/ubuntu disco-proposed main\n"
+        .parse::<SourcesList>()
+        .unwrap();
+    list.path = PathBuf::from("/etc/apt/sources.list");
+    let mut lists = SourcesLists::from_lists(vec![list]);
+
+    let desired: Vec<SourceEntry> = [
+        "deb http://x/ubuntu/ disco main",
+        "deb http://x/ubuntu disco-updates main",
+        "deb http://x/ubuntu disco-security main",
+        "deb http://ppa.launchpad.net/system76/pop/ubuntu disco main",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+
+    let plan = lists.plan(&desired);
+    let entry = |line: &str| line.parse::<SourceEntry>().unwrap();
+    let mut expected = Plan::default();
+    expected.changes.insert(
+        PathBuf::from("/etc/apt/sources.list"),
+        vec![
+            PlannedChange::Enable(SourceEntry {
+                enabled: false,
+                ..entry("deb http://x/ubuntu disco-updates main")
+            }),
+            PlannedChange::Insert(entry("deb http://x/ubuntu disco-security main")),
+            PlannedChange::Remove(entry("deb http://x/ubuntu disco-proposed main")),
+        ],
+    );
+    expected.changes.insert(
+        PathBuf::from("/etc/apt/sources.list.d/system76-ubuntu-pop-disco.list"),
+        vec![PlannedChange::Insert(desired[3].clone())],
+    );
+    assert_eq!(plan, expected);
+
+    // Planning does not change the lists.
+    assert!(lists.modified.is_empty());
+
+    lists.apply(plan).unwrap();
+    assert_eq!(lists.modified, vec![0, 1]);
+    assert_eq!(
+        lists[0].to_string(),
+        "deb http://x/ubuntu disco main\n\
+         deb http://x/ubuntu disco-updates main\n\
+         deb http://x/ubuntu disco-security main\n"
+    );
+    assert!(lists.plan(&desired).is_empty());
+
+    let stale = lists.plan(&desired[..1]);
+    lists.repo_modify("http://x/ubuntu", false);
+    match lists.apply(stale) {
+        Err(SourceError::EntryNotFound) => (),
+        other => panic!("expected a stale plan to fail, found {:?}", other),
+    }
+}
+
+#[test]
+fn consolidate() {
+    let mut lists = SourcesLists::from_lists(vec![
+        "deb http://x/ubuntu disco main\n\
+         deb http://x/ubuntu disco restricted main\n\
+         deb http://x/ubuntu disco universe\n\
+         deb-src http://x/ubuntu disco main\n\
+         deb [arch=amd64] http://x/ubuntu disco main\n\
+         # a comment between entries\n\
+         deb [arch=amd64] http://x/ubuntu disco multiverse\n\
+         deb [arch=amd64] http://x/ubuntu disco universe # kept apart\n\
+         \n\
+         # deb http://y/ubuntu disco main\n\
+         # deb http://y/ubuntu disco universe\n\
+         deb http://y/ubuntu disco multiverse\n"
+            .parse()
+            .unwrap(),
+        POP_PPA.parse().unwrap(),
+    ]);
+
+    assert_eq!(lists.consolidate(), 1);
+    assert_eq!(lists.modified, vec![0]);
+    assert_eq!(
+        lists[0].to_string(),
+        "deb http://x/ubuntu disco main restricted universe\n\
+         deb-src http://x/ubuntu disco main\n\
+         deb [arch=amd64] http://x/ubuntu disco main\n\
+         # a comment between entries\n\
+         deb [arch=amd64] http://x/ubuntu disco multiverse\n\
+         deb [arch=amd64] http://x/ubuntu disco universe # kept apart\n\
+         \n\
+         # deb http://y/ubuntu disco main universe\n\
+         deb http://y/ubuntu disco multiverse\n"
+    );
+
+    assert_eq!(lists.consolidate(), 0);
+}
@@ -0,0 +1,206 @@
+use super::*;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// A potential problem in the sources lists which does not prevent them from being parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SourceWarning {
+    /// A `deb` entry and its `deb-src` twin have differing components enabled.
+    SourceComponentMismatch {
+        url: String,
+        suite: String,
+        binary_components: Vec<String>,
+        source_components: Vec<String>,
+    },
+    /// Two URLs within the same file differ only by a trailing slash.
+    RedundantTrailingSlash { a: String, b: String, path: PathBuf },
+    /// An entry relaxes the verification of its repository with options like `allow-insecure`.
+    SecurityRelaxed { url: String, suite: String, options: Vec<String> },
+    /// An entry has an option whose value apt would not understand.
+    MalformedOption { url: String, suite: String, option: String },
+    /// An entry gives the same option more than once, where the last value wins.
+    DuplicateOption { url: String, suite: String, key: String },
+    /// An entry accepts expired Release files with `check-valid-until=no`.
+    ValidUntilUnchecked { url: String, suite: String },
+    /// The entry at this index of the list's `lines` is redundant, as another active entry of
+    /// the same kind, URL, suite, and options already has all of its components.
+    ShadowedEntry { path: PathBuf, index: usize },
+}
+
+impl Display for SourceWarning {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            SourceWarning::SourceComponentMismatch {
+                ref url,
+                ref suite,
+                ref binary_components,
+                ref source_components,
+            } => write!(
+                fmt,
+                "deb and deb-src entries for {} {} have different components: '{}' and '{}'",
+                url,
+                suite,
+                binary_components.join(" "),
+                source_components.join(" ")
+            ),
+            SourceWarning::RedundantTrailingSlash { ref a, ref b, ref path } => write!(
+                fmt,
+                "{:?} contains URLs which differ only by a trailing slash: {} and {}",
+                path, a, b
+            ),
+            SourceWarning::SecurityRelaxed { ref url, ref suite, ref options } => write!(
+                fmt,
+                "entry for {} {} relaxes verification with: {}",
+                url,
+                suite,
+                options.join(", ")
+            ),
+            SourceWarning::MalformedOption { ref url, ref suite, ref option } => {
+                write!(fmt, "entry for {} {} has a malformed option: '{}'", url, suite, option)
+            }
+            SourceWarning::DuplicateOption { ref url, ref suite, ref key } => {
+                write!(fmt, "entry for {} {} gives the '{}' option more than once", url, suite, key)
+            }
+            SourceWarning::ValidUntilUnchecked { ref url, ref suite } => {
+                write!(fmt, "entry for {} {} accepts expired Release files", url, suite)
+            }
+            SourceWarning::ShadowedEntry { ref path, index } => {
+                write!(fmt, "entry at index {} of {:?} is shadowed by another entry", index, path)
+            }
+        }
+    }
+}
+
+impl SourcesLists {
+    /// Checks the active entries for common mistakes.
+    ///
+    /// This is read-only; each problem found is returned as a `SourceWarning`.
+    pub fn validate(&self) -> Vec<SourceWarning> {
+        let mut warnings = Vec::new();
+        // Templated entries are not complete until their placeholders are expanded.
+        let entries = || self.active_entries().filter(|entry| !entry.is_templated());
+
+        for binary in entries().filter(|entry| !entry.source) {
+            let twin = entries().find(|entry| {
+                entry.source && entry.url() == binary.url() && entry.suite == binary.suite
+            });
+
+            if let Some(source) = twin {
+                if !same_components(&binary.components, &source.components) {
+                    warnings.push(SourceWarning::SourceComponentMismatch {
+                        url: binary.url.clone(),
+                        suite: binary.suite.clone(),
+                        binary_components: binary.components.clone(),
+                        source_components: source.components.clone(),
+                    });
+                }
+            }
+        }
+
+        for entry in entries() {
+            let relaxations = entry.security_relaxations();
+            if !relaxations.is_empty() {
+                warnings.push(SourceWarning::SecurityRelaxed {
+                    url: entry.url.clone(),
+                    suite: entry.suite.clone(),
+                    options: relaxations.into_iter().map(String::from).collect(),
+                });
+            }
+
+            for key in entry.duplicate_options() {
+                warnings.push(SourceWarning::DuplicateOption {
+                    url: entry.url.clone(),
+                    suite: entry.suite.clone(),
+                    key: key.to_owned(),
+                });
+            }
+
+            if entry.check_valid_until() == Some(false) {
+                warnings.push(SourceWarning::ValidUntilUnchecked {
+                    url: entry.url.clone(),
+                    suite: entry.suite.clone(),
+                });
+            }
+
+            for key in SECURITY_OPTIONS.iter().chain(&["check-valid-until"]) {
+                if let Some(Err(SourceError::InvalidValue { value, .. })) = entry.option_bool(key) {
+                    warnings.push(SourceWarning::MalformedOption {
+                        url: entry.url.clone(),
+                        suite: entry.suite.clone(),
+                        option: value,
+                    });
+                }
+            }
+        }
+
+        for list in self.iter() {
+            let mut urls: Vec<&str> = Vec::new();
+            for entry in list.lines.iter().filter_map(|line| match line {
+                SourceLine::Entry(entry) if !entry.is_templated() => Some(entry),
+                _ => None,
+            }) {
+                if urls.contains(&entry.url.as_str()) {
+                    continue;
+                }
+
+                if let Some(other) =
+                    urls.iter().find(|url| url.trim_end_matches('/') == entry.url())
+                {
+                    warnings.push(SourceWarning::RedundantTrailingSlash {
+                        a: (*other).to_owned(),
+                        b: entry.url.clone(),
+                        path: list.path.clone(),
+                    });
+                }
+
+                urls.push(&entry.url);
+            }
+        }
+
+        let located: Vec<(&Path, usize, &SourceEntry)> = self
+            .iter()
+            .flat_map(|list| {
+                list.lines.iter().enumerate().filter_map(move |(index, entry)| match entry {
+                    SourceLine::Entry(entry) if entry.enabled && !entry.is_templated() => {
+                        Some((list.path.as_path(), index, entry))
+                    }
+                    _ => None,
+                })
+            })
+            .collect();
+
+        for (id, &(path, index, entry)) in located.iter().enumerate() {
+            let shadowed = located.iter().enumerate().any(|(other_id, &(_, _, other))| {
+                other_id != id
+                    && subsumes(other, entry)
+                    // Of two identical entries, only the later one is redundant.
+                    && (other_id < id || !subsumes(entry, other))
+            });
+
+            if shadowed {
+                warnings.push(SourceWarning::ShadowedEntry { path: path.to_path_buf(), index });
+            }
+        }
+
+        warnings
+    }
+}
+
+/// Whether apt would fetch everything that `entry` provides through `other` as well.
+fn subsumes(other: &SourceEntry, entry: &SourceEntry) -> bool {
+    fn sorted_options(entry: &SourceEntry) -> Vec<&str> {
+        let mut options = entry.option_tokens();
+        options.sort();
+        options
+    }
+
+    other.source == entry.source
+        && other.url() == entry.url()
+        && other.suite == entry.suite
+        && entry.components.iter().all(|component| other.components.contains(component))
+        && sorted_options(other) == sorted_options(entry)
+}
+
+fn same_components(a: &[String], b: &[String]) -> bool {
+    a.iter().all(|c| b.contains(c)) && b.iter().all(|c| a.contains(c))
+}